@@ -0,0 +1,145 @@
+//! Automatic frequency control (AFC) for tracking targets with drifting frequencies.
+
+use std::collections::VecDeque;
+
+use common::AfcConfig;
+
+/// Tracks the frequency of a single target using two additional filter bins placed either side of
+/// the target frequency.
+pub struct Afc {
+    config: AfcConfig,
+
+    /// The distance (in Hz) between the target bin and each of the side bins
+    spacing: f32,
+
+    /// The current estimate of the offset (in Hz) of the target from its configured frequency
+    offset: f32,
+
+    /// The offset (in Hz) that the center bin is actually measuring, which may differ from
+    /// `offset` if the filter only supports a fixed set of frequencies
+    tuned: f32,
+
+    /// The magnitudes of the lower, center and upper bins for the most recent windows
+    history: VecDeque<[f32; 3]>,
+
+    /// The maximum number of windows to keep in `history`
+    history_length: usize,
+
+    /// Whether the filter bins need to be moved to the new offset
+    retune: bool,
+}
+
+impl Afc {
+    /// Create a new AFC stage, where `spacing` is the distance (in Hz) between the filter bins and
+    /// `history` is the number of windows that need to be kept to cover a pulse.
+    pub fn new(config: AfcConfig, spacing: f32, history: usize) -> Afc {
+        Afc {
+            config,
+            spacing,
+            offset: 0.0,
+            tuned: 0.0,
+            history: VecDeque::with_capacity(history + 1),
+            history_length: history + 1,
+            retune: false,
+        }
+    }
+
+    /// The offsets (in Hz) of the lower, center and upper bins from the configured frequency
+    pub fn bin_offsets(&self) -> [f32; 3] {
+        [self.offset - self.spacing, self.offset, self.offset + self.spacing]
+    }
+
+    /// Returns the new bin offsets if the bins need to be retuned.
+    pub fn take_retune(&mut self) -> Option<[f32; 3]> {
+        if !self.retune {
+            return None;
+        }
+        self.retune = false;
+        Some(self.bin_offsets())
+    }
+
+    /// Sets the offset (in Hz) that the center bin is actually measuring
+    pub fn set_tuned(&mut self, offset: f32) {
+        self.tuned = offset;
+    }
+
+    /// Discard the magnitudes of previous windows, keeping the current frequency estimate
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Add the magnitudes of the lower, center and upper bins for the current window
+    pub fn input(&mut self, magnitudes: [f32; 3]) {
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(magnitudes);
+    }
+
+    /// Estimate the frequency offset of a pulse that started `start_offset` windows ago and lasted
+    /// for `duration` windows, then move the bins towards the estimate. Returns the estimated
+    /// offset (in Hz) of the pulse from the configured frequency.
+    pub fn pulse(&mut self, start_offset: usize, duration: usize) -> f32 {
+        // Skip the windows containing the edges of the pulse
+        let (skip, take) = if duration > 2 { (1, duration - 2) } else { (0, duration.max(1)) };
+
+        let newest = self.history.len();
+        let start = newest.saturating_sub(start_offset + 1) + skip;
+        let (lower, center, upper) = self.history.iter()
+            .skip(start)
+            .take(take)
+            .fold((0.0, 0.0, 0.0), |acc, x| (acc.0 + x[0], acc.1 + x[1], acc.2 + x[2]));
+
+        // Fit a parabola through the three bins, the peak is only valid if it lies between the
+        // outer bins.
+        let denominator = lower - 2.0 * center + upper;
+        if denominator >= 0.0 {
+            return self.tuned;
+        }
+        let peak = (0.5 * (lower - upper) / denominator).max(-1.0).min(1.0);
+        let estimate = self.tuned + peak * self.spacing;
+
+        let max_offset = self.config.max_offset.abs();
+        let new_offset = self.offset + self.config.rate * (estimate - self.offset);
+        self.offset = new_offset.max(-max_offset).min(max_offset);
+        self.retune = true;
+
+        estimate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tracks_peak() {
+        let config = AfcConfig { max_offset: 500.0, rate: 0.5 };
+        let mut afc = Afc::new(config, 100.0, 10);
+
+        // A pulse with a peak 40 Hz above the center bin, surrounded by noise
+        for _ in 0..3 {
+            afc.input([1.0, 1.0, 1.0]);
+        }
+        let response = |x: f32| 10.0 - (x - 40.0).powi(2) / 1000.0;
+        for _ in 0..5 {
+            afc.input([response(-100.0), response(0.0), response(100.0)]);
+        }
+        afc.input([1.0, 1.0, 1.0]);
+
+        let estimate = afc.pulse(5, 5);
+        assert!((estimate - 40.0).abs() < 1e-3, "{}", estimate);
+        let bins = afc.take_retune().unwrap();
+        assert!((bins[1] - 20.0).abs() < 1e-3 && (bins[2] - bins[1] - 100.0).abs() < 1e-3);
+        assert_eq!(afc.take_retune(), None);
+
+        // The offset is limited to the maximum offset
+        afc.input([0.0, 5.0, 6.0]);
+        for _ in 0..20 {
+            afc.pulse(0, 1);
+            let bins = afc.take_retune().unwrap();
+            afc.set_tuned(bins[1]);
+        }
+        assert_eq!(afc.bin_offsets()[1], 500.0);
+    }
+}
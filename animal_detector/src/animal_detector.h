@@ -22,6 +22,21 @@ typedef struct {
     float threshold;
     int64_t edge_length;
     int64_t peak_lookahead;
+    float gain;
+    /* Set `cfar_window` to a positive value (in seconds) to enable adaptive thresholding */
+    float cfar_window;
+    float cfar_multiplier;
+    /* Set `period` to a positive value (in seconds) if the pulse period of the target is known */
+    float period;
+    /* Set `mortality_period` to a positive value (in seconds) if the tag has a mortality mode */
+    float mortality_period;
+    float period_variance;
+    bool filter_by_period;
+    /* Set `afc_max_offset` to a positive value (in Hz) to track drifting target frequencies */
+    float afc_max_offset;
+    float afc_rate;
+    /* Set `matched_filter` to detect pulses with a matched filter instead of edge detection */
+    bool matched_filter;
 } PulseTarget;
 
 typedef struct {
@@ -33,7 +48,12 @@ typedef struct {
 typedef struct {
     float freq;
     float signal_strength;
-    uint32_t gain;
+    /* `noise_floor` and `snr` (in dB) are NaN if the noise floor has not been estimated yet */
+    float noise_floor;
+    float snr;
+    /* NaN if this is the first pulse detected for the target */
+    float period;
+    float gain;
     uint64_t seconds;
     uint32_t nanos;
 } Pulse;
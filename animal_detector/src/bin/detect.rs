@@ -0,0 +1,307 @@
+//! Runs the detectors over a recording of raw I/Q samples, writing one line of JSON for each
+//! detected pulse. Timestamps are relative to the first sample of the recording.
+//!
+//! The configuration file has the same format as the pulse server task configuration:
+//! `{ "sdr_config": { .. }, "pulse_targets": [ .. ] }`. For SigMF recordings the sample rate,
+//! center frequency and sample format are taken from the recording's metadata.
+//!
+//! `--overlap <n>` analyses the recording with `n` overlapping windows covering each sample, which
+//! gives more accurate pulse timing and durations at `n` times the processing cost.
+//!
+//! With `--annotate <file>`, the detected pulses are also written as annotations to a SigMF
+//! metadata file, so the recording and the detections can be inspected together.
+//!
+//! # Options
+//!
+//! The configuration file may also enable these stages, which are all disabled by default:
+//!
+//! - `"iq_correction": { "dc_window": 0.1, "imbalance_window": 1.0 }` removes the DC offset and
+//!   I/Q imbalance of the receiver.
+//! - `"blanker": { "threshold": 20.0, "hold": 5e-6, "max_duration": 2e-4, "window": 0.05 }` blanks
+//!   short impulses before the signal reaches the detectors.
+//! - `"cross_target_rejection": 0.5` drops pulses detected by more than this fraction of the
+//!   targets at once.
+//! - `"leakage_check": { "margin": 6.0, "suppress": false }` flags (or drops) pulses that may be
+//!   leakage of a stronger pulse from a target with a nearby frequency. This overrides the
+//!   `leakage_check` of `sdr_config`.
+//!
+//! The targets may set `shape_limits` and `duration_range` as for the pulse server. The `shape` of
+//! each pulse is included in the output, which can be used to choose the shape limits.
+//!
+//! Once the recording has been processed, a summary of each enabled option is printed to stderr:
+//! the interference that was removed, the number of pulses dropped by the shape limits, the final
+//! I/Q correction estimates, and for targets with a `duration_range`, the latest measured duration
+//! distribution with the suggested `duration` and `duration_variance`.
+
+extern crate animal_detector;
+extern crate common;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use std::{env, process};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use animal_detector::{Detectors, DetectorsBuilder};
+use animal_detector::blanker::BlankerConfig;
+use animal_detector::iq_correction::IqCorrectionConfig;
+use animal_detector::leakage::LeakageConfig;
+use animal_detector::sample_format::{self, SampleFormat};
+use animal_detector::sigmf::{self, Metadata};
+use common::{Pulse, PulseTarget, SdrConfig, Timestamp};
+
+const USAGE: &str = "Usage: detect <config.json> <recording> [--format cu8|cs8|cs16|cf32] \
+    [--overlap <n>] [--output <file>] [--annotate <file.sigmf-meta>]";
+
+/// The number of samples processed at a time
+const BUFFER_SAMPLES: usize = 1 << 16;
+
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    sdr_config: SdrConfig,
+    pulse_targets: Vec<PulseTarget>,
+    #[serde(default)]
+    blanker: Option<BlankerConfig>,
+    #[serde(default)]
+    cross_target_rejection: Option<f32>,
+    #[serde(default)]
+    iq_correction: Option<IqCorrectionConfig>,
+    #[serde(default)]
+    leakage_check: Option<LeakageConfig>,
+}
+
+#[derive(Serialize)]
+struct DetectedPulse {
+    /// The index of the first sample of the pulse in the recording
+    sample: u64,
+
+    #[serde(flatten)]
+    pulse: Pulse,
+}
+
+struct Args {
+    config: PathBuf,
+    recording: PathBuf,
+    format: Option<SampleFormat>,
+    overlap: usize,
+    output: Option<PathBuf>,
+    annotate: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+        let mut positional = vec![];
+        let mut format = None;
+        let mut overlap = 1;
+        let mut output = None;
+        let mut annotate = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    let value = args.next().ok_or("Missing value for `--format`")?;
+                    format = Some(value.parse()?);
+                }
+                "--overlap" => {
+                    let value = args.next().ok_or("Missing value for `--overlap`")?;
+                    overlap = value.parse().map_err(|_| format!("Invalid overlap: {}", value))?;
+                }
+                "--output" => {
+                    output = Some(PathBuf::from(args.next().ok_or("Missing value for `--output`")?));
+                }
+                "--annotate" => {
+                    let value = args.next().ok_or("Missing value for `--annotate`")?;
+                    annotate = Some(PathBuf::from(value));
+                }
+                "--help" | "-h" => return Err(USAGE.into()),
+                _ => positional.push(PathBuf::from(arg)),
+            }
+        }
+
+        if positional.len() != 2 {
+            return Err(USAGE.into());
+        }
+        let recording = positional.pop().unwrap();
+        let config = positional.pop().unwrap();
+
+        Ok(Args { config, recording, format, overlap, output, annotate })
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(env::args().skip(1))?;
+
+    let mut config: Config = {
+        let file = File::open(&args.config)
+            .map_err(|e| format!("Failed to open `{}`: {}", args.config.display(), e))?;
+        serde_json::from_reader(BufReader::new(file))?
+    };
+
+    let (data_path, metadata) = if sigmf::is_sigmf(&args.recording) {
+        let metadata = Metadata::read(&args.recording)?;
+        metadata.apply(&mut config.sdr_config)?;
+        (sigmf::data_path(&args.recording), Some(metadata))
+    }
+    else {
+        (args.recording.clone(), None)
+    };
+
+    if let Some(format) = args.format {
+        config.sdr_config.sample_format = format;
+    }
+    if config.sdr_config.samp_rate == 0 {
+        return Err("`samp_rate` must be set in the configuration".into());
+    }
+
+    let output: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    // Annotations are added to a copy of the recording's metadata, or to new metadata (without a
+    // capture time) for raw recordings.
+    let mut annotations = args.annotate.as_ref().map(|_| {
+        let mut metadata = metadata.unwrap_or_else(|| {
+            let mut metadata = Metadata::new(&config.sdr_config, Timestamp { seconds: 0, nanos: 0 });
+            metadata.captures[0].datetime = None;
+            metadata
+        });
+        metadata.annotations.clear();
+        metadata
+    });
+
+    let mut builder = DetectorsBuilder::new().overlap(args.overlap);
+    if let Some(blanker) = config.blanker {
+        builder = builder.noise_blanker(blanker);
+    }
+    if let Some(fraction) = config.cross_target_rejection {
+        builder = builder.cross_target_rejection(fraction);
+    }
+    if let Some(correction) = config.iq_correction {
+        builder = builder.iq_correction(correction);
+    }
+    if let Some(leakage) = config.leakage_check {
+        builder = builder.leakage_check(leakage);
+    }
+    let mut detectors = builder.build(&config.sdr_config, &config.pulse_targets);
+    for target in detectors.rejected_targets() {
+        eprintln!("Ignoring target {}: {:?}", target.target_id, target.reason);
+    }
+    let mut output = BufWriter::new(output);
+    detect(&mut detectors, &config.sdr_config, &data_path, &mut output, annotations.as_mut())?;
+
+    let interference_checks = config.blanker.is_some()
+        || config.cross_target_rejection.is_some()
+        || config.leakage_check.is_some()
+        || config.sdr_config.leakage_check.is_some();
+    if interference_checks {
+        let stats = detectors.stats();
+        eprintln!(
+            "Blanked {} impulses ({} samples, {:.3}% of the recording), rejected {} pulses, \
+            dropped {} pulses as leakage",
+            stats.blanker.impulses,
+            stats.blanker.blanked_samples,
+            100.0 * stats.blanker.blanked_fraction(),
+            stats.rejected_pulses,
+            stats.leakage_pulses,
+        );
+    }
+    if config.pulse_targets.iter().any(|x| x.shape_limits.is_some()) {
+        eprintln!("Dropped {} pulses outside of the shape limits", detectors.stats().shape_pulses);
+    }
+    if let Some(estimate) = detectors.iq_estimate() {
+        eprintln!(
+            "DC offset: ({:.4}, {:.4}), gain imbalance: {:.2} dB, phase imbalance: {:.2}°",
+            estimate.dc_offset.0,
+            estimate.dc_offset.1,
+            estimate.gain_imbalance,
+            estimate.phase_imbalance,
+        );
+    }
+
+    if let (Some(path), Some(metadata)) = (args.annotate, annotations) {
+        metadata.write(&path)?;
+    }
+    Ok(())
+}
+
+fn detect(
+    detectors: &mut Detectors,
+    sdr_config: &SdrConfig,
+    data_path: &Path,
+    output: &mut dyn Write,
+    mut annotations: Option<&mut Metadata>,
+) -> Result<(), Box<dyn Error>> {
+    let sample_size = sdr_config.sample_format.sample_size();
+
+    detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+    let mut input = File::open(data_path)
+        .map_err(|e| format!("Failed to open `{}`: {}", data_path.display(), e))?;
+    let mut buffer = vec![0; BUFFER_SAMPLES * sample_size];
+    let mut durations = BTreeMap::new();
+
+    loop {
+        let length = sample_format::read_samples(&mut input, &mut buffer, sdr_config.sample_format)?;
+        if length == 0 {
+            break;
+        }
+
+        for pulse in detectors.next_bytes(&buffer[..length]) {
+            let sample = timestamp_to_sample(pulse.timestamp, sdr_config.samp_rate);
+            if let Some(ref mut metadata) = annotations {
+                add_annotation(metadata, pulse);
+            }
+            serde_json::to_writer(&mut *output, &DetectedPulse { sample, pulse })?;
+            writeln!(output)?;
+        }
+        for report in detectors.duration_reports() {
+            durations.insert(report.target_id, report);
+        }
+    }
+
+    for report in durations.values() {
+        eprintln!(
+            "Target {}: {} pulses with a median duration of {:.4} s ({:.4} to {:.4} s), suggested duration: \
+            {:.4} s, duration_variance: {:.4} s",
+            report.target_id,
+            report.count,
+            report.median,
+            report.min,
+            report.max,
+            report.suggested_duration,
+            report.suggested_variance,
+        );
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// Annotate `metadata` with a pulse timestamped relative to the start of the recording
+fn add_annotation(metadata: &mut Metadata, mut pulse: Pulse) {
+    if let Some(start_time) = metadata.start_time() {
+        let offset = pulse.timestamp.seconds as u128 * 1_000_000_000 + pulse.timestamp.nanos as u128;
+        pulse.timestamp = start_time.add_nanos(offset as u64);
+    }
+    metadata.add_pulse(&pulse);
+}
+
+/// Converts a timestamp relative to the start of the recording into a sample index
+fn timestamp_to_sample(timestamp: Timestamp, samp_rate: u64) -> u64 {
+    let nanos = timestamp.seconds as u128 * 1_000_000_000 + timestamp.nanos as u128;
+    ((nanos * samp_rate as u128 + 500_000_000) / 1_000_000_000) as u64
+}
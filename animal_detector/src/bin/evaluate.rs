@@ -0,0 +1,418 @@
+//! Measures the detection performance of a set of detector parameters, by running the detectors
+//! over signals with known pulses and reporting the detection probability, false alarm rate and
+//! timing errors for each combination of parameters. The parameters that can be swept are
+//! `algorithm`, `edge_length`, `peak_lookahead`, `threshold` and `duration_variance`.
+//!
+//! The configuration file contains a template `target` whose parameters are swept, the `signal`
+//! to evaluate against, and the parameter values to `sweep`:
+//!
+//! ```json
+//! {
+//!     "target": { "freq": 0, "duration": 0.02, "duration_variance": 0.004, "threshold": 0.1, .. },
+//!     "signal": {
+//!         "synthetic": {
+//!             "generator": { "samp_rate": 1024000, "tags": [..], "noise": 0.1, "seed": 1 },
+//!             "center_freq": 150000000,
+//!             "duration": 60.0,
+//!             "snr": [-40, -35, -30]
+//!         }
+//!     },
+//!     "sweep": { "algorithm": ["edge", "matched_filter"], "threshold": [0.05, 0.1] },
+//!     "tolerance": 0.005
+//! }
+//! ```
+//!
+//! Synthetic signals create one target for each tag. When `snr` is given, the amplitude of every
+//! tag is set so that the ratio of pulse power to noise power across the full sample bandwidth
+//! matches each SNR (in dB) in turn. Recorded signals are given as
+//! `{ "recording": { "path": .., "truth": .., "freqs": [..], "sdr_config": {..} } }`, where
+//! `truth` is a JSON list of the pulses in the recording (in the format produced by
+//! `signal_gen::TruePulse`) and `freqs` are the frequencies of the targets.
+
+extern crate animal_detector;
+extern crate common;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use std::{env, process};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use animal_detector::Detectors;
+use animal_detector::evaluation::{self, Evaluation};
+use animal_detector::sample_format;
+use animal_detector::sigmf::{self, Metadata};
+use animal_detector::signal_gen::{Generator, GeneratorConfig, TruePulse};
+use common::{DetectionAlgorithm, Pulse, PulseTarget, SdrConfig, Timestamp};
+
+const USAGE: &str = "Usage: evaluate <config.json> [--format csv|json] [--output <file>]";
+
+/// The number of samples processed at a time
+const BUFFER_SAMPLES: usize = 1 << 16;
+
+fn default_tolerance() -> f32 {
+    0.005
+}
+
+#[derive(Deserialize)]
+struct Config {
+    target: PulseTarget,
+    signal: Signal,
+
+    #[serde(default)]
+    sweep: Sweep,
+
+    /// The maximum difference (in seconds) between the start of a detection and a true pulse for
+    /// them to match
+    #[serde(default = "default_tolerance")]
+    tolerance: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Signal {
+    Synthetic {
+        generator: GeneratorConfig,
+        center_freq: u64,
+
+        /// The length (in seconds) of the signal
+        duration: f32,
+
+        #[serde(default)]
+        snr: Vec<f32>,
+    },
+    Recording {
+        path: PathBuf,
+        truth: PathBuf,
+        freqs: Vec<f32>,
+
+        #[serde(default)]
+        sdr_config: SdrConfig,
+    },
+}
+
+/// The values of each parameter to evaluate, parameters without any values use the value from
+/// the template target.
+#[derive(Default, Deserialize)]
+struct Sweep {
+    #[serde(default)]
+    edge_length: Vec<i64>,
+
+    #[serde(default)]
+    peak_lookahead: Vec<i64>,
+
+    #[serde(default)]
+    threshold: Vec<f32>,
+
+    #[serde(default)]
+    duration_variance: Vec<f32>,
+
+    #[serde(default)]
+    algorithm: Vec<DetectionAlgorithm>,
+}
+
+impl Sweep {
+    /// Every combination of the parameters applied to `template`
+    fn targets(&self, template: &PulseTarget) -> Vec<PulseTarget> {
+        fn or<T: Copy>(values: &[T], default: T) -> Vec<T> {
+            if values.is_empty() { vec![default] } else { values.to_vec() }
+        }
+
+        let mut targets = vec![];
+        for &algorithm in &or(&self.algorithm, template.algorithm) {
+            for &edge_length in &or(&self.edge_length, template.edge_length) {
+                for &peak_lookahead in &or(&self.peak_lookahead, template.peak_lookahead) {
+                    for &threshold in &or(&self.threshold, template.threshold) {
+                        for &duration_variance in &or(&self.duration_variance, template.duration_variance) {
+                            targets.push(PulseTarget {
+                                algorithm,
+                                edge_length,
+                                peak_lookahead,
+                                threshold,
+                                duration_variance,
+                                ..template.clone()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        targets
+    }
+}
+
+#[derive(Serialize)]
+struct Row {
+    snr: Option<f32>,
+    algorithm: DetectionAlgorithm,
+    edge_length: i64,
+    peak_lookahead: i64,
+    threshold: f32,
+    duration_variance: f32,
+    detection_probability: f32,
+    false_alarm_rate: f32,
+
+    #[serde(flatten)]
+    evaluation: Evaluation,
+}
+
+const CSV_HEADER: &str = "snr,algorithm,edge_length,peak_lookahead,threshold,duration_variance,\
+    detection_probability,false_alarms_per_minute,true_pulses,detected,false_alarms,\
+    timing_error,duration_error";
+
+impl Row {
+    fn new(snr: Option<f32>, target: &PulseTarget, evaluation: Evaluation) -> Row {
+        Row {
+            snr,
+            algorithm: target.algorithm,
+            edge_length: target.edge_length,
+            peak_lookahead: target.peak_lookahead,
+            threshold: target.threshold,
+            duration_variance: target.duration_variance,
+            detection_probability: evaluation.detection_probability(),
+            false_alarm_rate: evaluation.false_alarm_rate(),
+            evaluation,
+        }
+    }
+
+    fn write_csv(&self, output: &mut dyn Write) -> io::Result<()> {
+        let snr = self.snr.map(|x| x.to_string()).unwrap_or_default();
+        let algorithm = match self.algorithm {
+            DetectionAlgorithm::Edge => "edge",
+            DetectionAlgorithm::MatchedFilter => "matched_filter",
+        };
+        writeln!(
+            output,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            snr,
+            algorithm,
+            self.edge_length,
+            self.peak_lookahead,
+            self.threshold,
+            self.duration_variance,
+            self.detection_probability,
+            self.false_alarm_rate,
+            self.evaluation.true_pulses,
+            self.evaluation.detected,
+            self.evaluation.false_alarms,
+            self.evaluation.timing_error,
+            self.evaluation.duration_error,
+        )
+    }
+}
+
+/// The detectors for one combination of parameters
+struct Trial {
+    target: PulseTarget,
+    detectors: Detectors,
+    pulses: Vec<Pulse>,
+}
+
+impl Trial {
+    fn new(sdr_config: &SdrConfig, freqs: &[f32], target: PulseTarget) -> Trial {
+        // Pulses are matched to frequencies by the index of their target
+        let targets: Vec<_> = freqs.iter().map(|&freq| PulseTarget { id: None, freq, ..target.clone() }).collect();
+        let mut detectors = Detectors::new(sdr_config, &targets);
+        detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+        Trial { target, detectors, pulses: vec![] }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Args {
+    config: PathBuf,
+    format: Format,
+    output: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+        let mut config = None;
+        let mut format = Format::Csv;
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    format = match args.next().as_ref().map(|x| x.as_str()) {
+                        Some("csv") => Format::Csv,
+                        Some("json") => Format::Json,
+                        _ => return Err("`--format` must be `csv` or `json`".into()),
+                    };
+                }
+                "--output" => {
+                    output = Some(PathBuf::from(args.next().ok_or("Missing value for `--output`")?));
+                }
+                "--help" | "-h" => return Err(USAGE.into()),
+                _ if config.is_none() => config = Some(PathBuf::from(arg)),
+                _ => return Err(USAGE.into()),
+            }
+        }
+
+        Ok(Args { config: config.ok_or(USAGE)?, format, output })
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(env::args().skip(1))?;
+
+    let config: Config = {
+        let file = File::open(&args.config)
+            .map_err(|e| format!("Failed to open `{}`: {}", args.config.display(), e))?;
+        serde_json::from_reader(BufReader::new(file))?
+    };
+
+    let rows = match config.signal {
+        Signal::Synthetic { ref generator, center_freq, duration, ref snr } => {
+            evaluate_synthetic(&config, generator, center_freq, duration, snr)
+        }
+        Signal::Recording { ref path, ref truth, ref freqs, ref sdr_config } => {
+            evaluate_recording(&config, path, truth, freqs, sdr_config.clone())?
+        }
+    };
+
+    let output: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut output = BufWriter::new(output);
+
+    match args.format {
+        Format::Csv => {
+            writeln!(output, "{}", CSV_HEADER)?;
+            for row in &rows {
+                row.write_csv(&mut output)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut output, &rows)?;
+            writeln!(output)?;
+        }
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+fn evaluate_synthetic(
+    config: &Config,
+    generator: &GeneratorConfig,
+    center_freq: u64,
+    duration: f32,
+    snrs: &[f32],
+) -> Vec<Row> {
+    let sdr_config = SdrConfig { samp_rate: generator.samp_rate, center_freq, ..SdrConfig::default() };
+    let freqs: Vec<_> = generator.tags.iter().map(|x| center_freq as f32 + x.freq).collect();
+    let num_samples = (duration as f64 * generator.samp_rate as f64) as u64;
+
+    let snrs: Vec<_> = if snrs.is_empty() { vec![None] } else { snrs.iter().map(|&x| Some(x)).collect() };
+
+    let mut rows = vec![];
+    for snr in snrs {
+        let mut generator_config = generator.clone();
+        if let Some(snr) = snr {
+            let amplitude = generator.noise * 10.0_f32.powf(snr / 20.0);
+            for tag in &mut generator_config.tags {
+                tag.amplitude = amplitude;
+            }
+        }
+
+        let mut generator = Generator::new(generator_config);
+        let mut trials: Vec<_> = config.sweep.targets(&config.target).into_iter()
+            .map(|target| Trial::new(&sdr_config, &freqs, target))
+            .collect();
+
+        let mut truth = vec![];
+        let mut buffer = vec![0.0; BUFFER_SAMPLES * 2];
+        while generator.sample_index() < num_samples {
+            let remaining = (num_samples - generator.sample_index()) as usize;
+            let buffer = &mut buffer[..2 * remaining.min(BUFFER_SAMPLES)];
+
+            truth.extend(generator.fill(buffer));
+            for trial in &mut trials {
+                let pulses = trial.detectors.next_f32(buffer);
+                trial.pulses.extend(pulses);
+            }
+        }
+
+        for trial in trials {
+            let evaluation = evaluation::evaluate(
+                &truth, &trial.pulses, sdr_config.samp_rate, duration, config.tolerance
+            );
+            rows.push(Row::new(snr, &trial.target, evaluation));
+        }
+    }
+
+    rows
+}
+
+fn evaluate_recording(
+    config: &Config,
+    path: &Path,
+    truth_path: &Path,
+    freqs: &[f32],
+    mut sdr_config: SdrConfig,
+) -> Result<Vec<Row>, Box<dyn Error>> {
+    let data_path = if sigmf::is_sigmf(path) {
+        Metadata::read(path)?.apply(&mut sdr_config)?;
+        sigmf::data_path(path)
+    }
+    else {
+        path.to_owned()
+    };
+    if sdr_config.samp_rate == 0 {
+        return Err("`samp_rate` must be set in the configuration".into());
+    }
+
+    let truth: Vec<TruePulse> = {
+        let file = File::open(truth_path)
+            .map_err(|e| format!("Failed to open `{}`: {}", truth_path.display(), e))?;
+        serde_json::from_reader(BufReader::new(file))?
+    };
+
+    let mut trials: Vec<_> = config.sweep.targets(&config.target).into_iter()
+        .map(|target| Trial::new(&sdr_config, freqs, target))
+        .collect();
+
+    let mut input = File::open(&data_path)
+        .map_err(|e| format!("Failed to open `{}`: {}", data_path.display(), e))?;
+    let mut buffer = vec![0; BUFFER_SAMPLES * sdr_config.sample_format.sample_size()];
+    loop {
+        let length = sample_format::read_samples(&mut input, &mut buffer, sdr_config.sample_format)?;
+        if length == 0 {
+            break;
+        }
+        for trial in &mut trials {
+            let pulses = trial.detectors.next_bytes(&buffer[..length]);
+            trial.pulses.extend(pulses);
+        }
+    }
+
+    let num_samples = trials.first().map_or(0, |x| x.detectors.sample_index());
+    let duration = (num_samples as f64 / sdr_config.samp_rate as f64) as f32;
+
+    Ok(trials.into_iter()
+        .map(|trial| {
+            let evaluation = evaluation::evaluate(
+                &truth, &trial.pulses, sdr_config.samp_rate, duration, config.tolerance
+            );
+            Row::new(None, &trial.target, evaluation)
+        })
+        .collect())
+}
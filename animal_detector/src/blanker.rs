@@ -0,0 +1,238 @@
+//! A noise blanker that removes short broadband impulses (e.g. from motor controllers or
+//! telemetry radios) from the raw I/Q stream before it is filtered.
+
+use std::collections::VecDeque;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct BlankerConfig {
+    /// Samples with a power above this multiple of the average power are considered impulses
+    pub threshold: f32,
+
+    /// The time (in seconds) blanked either side of each impulse
+    pub hold: f32,
+
+    /// The maximum duration (in seconds) of an impulse. Longer bursts of power are assumed to be
+    /// signals (e.g. a strong pulse from a nearby tag) and are not blanked.
+    pub max_duration: f32,
+
+    /// The time constant (in seconds) used to estimate the average power of the signal
+    pub window: f32,
+}
+
+impl Default for BlankerConfig {
+    fn default() -> BlankerConfig {
+        BlankerConfig { threshold: 20.0, hold: 5e-6, max_duration: 2e-4, window: 0.05 }
+    }
+}
+
+/// Statistics about the impulses removed from the signal by the noise blanker
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlankerStats {
+    /// The total number of samples processed
+    pub samples: u64,
+
+    /// The number of samples that were blanked
+    pub blanked_samples: u64,
+
+    /// The number of impulses that were blanked
+    pub impulses: u64,
+}
+
+impl BlankerStats {
+    /// The fraction of the signal that was blanked
+    pub fn blanked_fraction(&self) -> f32 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.blanked_samples as f32 / self.samples as f32
+    }
+}
+
+/// Detects impulses as short bursts of samples with a power well above the average power, and
+/// replaces them (and the samples either side) with zeros. Samples are delayed by `delay()`
+/// samples, so that the length of each burst is known before deciding whether to blank it.
+pub struct NoiseBlanker {
+    threshold: f32,
+    hold: u64,
+    max_length: u64,
+
+    /// The smoothing factor used to update the average power
+    alpha: f32,
+
+    /// The average power of the samples that are not part of an impulse
+    average: f32,
+
+    /// The samples that have not been output yet
+    delay_line: VecDeque<(f32, f32)>,
+    delay: usize,
+
+    /// The index of the next input sample
+    index: u64,
+
+    /// The index of the first sample and the length of the current burst of high power samples
+    burst: Option<(u64, u64)>,
+
+    /// The ranges of sample indices (inclusive) that will be blanked when they are output
+    blank_ranges: VecDeque<(u64, u64)>,
+
+    stats: BlankerStats,
+}
+
+impl NoiseBlanker {
+    pub fn new(config: BlankerConfig, samp_rate: f32) -> NoiseBlanker {
+        let hold = (config.hold * samp_rate).round().max(0.0) as u64;
+        let max_length = (config.max_duration * samp_rate).round().max(1.0) as u64;
+        let window = (config.window * samp_rate).max(1.0);
+        let delay = (max_length + hold + 1) as usize;
+
+        NoiseBlanker {
+            threshold: config.threshold,
+            hold,
+            max_length,
+            alpha: 1.0 / window,
+            average: 0.0,
+            delay_line: VecDeque::with_capacity(delay + 1),
+            delay,
+            index: 0,
+            burst: None,
+            blank_ranges: VecDeque::new(),
+            stats: BlankerStats::default(),
+        }
+    }
+
+    /// The number of samples that the output is delayed by
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    pub fn stats(&self) -> &BlankerStats {
+        &self.stats
+    }
+
+    /// Process the next sample, returning the sample from `delay()` samples ago (or zero if it was
+    /// blanked).
+    pub fn input(&mut self, i: f32, q: f32) -> (f32, f32) {
+        let index = self.index;
+        self.index += 1;
+        self.stats.samples += 1;
+
+        let power = i * i + q * q;
+        let warmed_up = index as f32 * self.alpha >= 1.0;
+
+        if warmed_up && power > self.threshold * self.average {
+            let burst = self.burst.get_or_insert((index, 0));
+            burst.1 += 1;
+        }
+        else {
+            if let Some((start, length)) = self.burst.take() {
+                if length <= self.max_length {
+                    self.blank_ranges.push_back((start.saturating_sub(self.hold), index - 1 + self.hold));
+                    self.stats.impulses += 1;
+                }
+            }
+
+            // The average is estimated from the first samples until the window is filled
+            let alpha = if warmed_up { self.alpha } else { 1.0 / (index + 1) as f32 };
+            self.average += alpha * (power - self.average);
+        }
+
+        self.delay_line.push_back((i, q));
+        if self.delay_line.len() <= self.delay {
+            return (0.0, 0.0);
+        }
+
+        let output_index = index - self.delay as u64;
+        let sample = self.delay_line.pop_front().unwrap();
+
+        while self.blank_ranges.front().map_or(false, |x| x.1 < output_index) {
+            self.blank_ranges.pop_front();
+        }
+        if self.blank_ranges.front().map_or(false, |x| x.0 <= output_index) {
+            self.stats.blanked_samples += 1;
+            return (0.0, 0.0);
+        }
+
+        sample
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::slice;
+
+    use common::{SdrConfig, Timestamp};
+    use evaluation;
+    use signal_gen::{tag_target, Generator, GeneratorConfig, InterferenceConfig, TagConfig};
+    use DetectorsBuilder;
+
+    #[test]
+    fn blanks_short_impulses() {
+        let config = BlankerConfig { threshold: 10.0, hold: 2.0, max_duration: 5.0, window: 100.0 };
+        let mut blanker = NoiseBlanker::new(config, 1.0);
+        let delay = blanker.delay();
+
+        // A constant signal with a 3 sample impulse, and a longer burst that should be kept
+        let input: Vec<f32> = (0..400)
+            .map(|n| match n {
+                200..=202 => 10.0,
+                300..=319 => 10.0,
+                _ => 1.0,
+            })
+            .collect();
+
+        let output: Vec<f32> = input.iter()
+            .chain(&[1.0; 32])
+            .map(|&x| blanker.input(x, 0.0).0)
+            .skip(delay)
+            .collect();
+
+        let blanked: Vec<_> = (0..400).filter(|&n| output[n] == 0.0).collect();
+        assert_eq!(blanked, (198..=204).collect::<Vec<_>>());
+        assert_eq!(&output[300..320], &input[300..320]);
+
+        let stats = blanker.stats();
+        assert_eq!(stats.impulses, 1);
+        assert_eq!(stats.blanked_samples, 7);
+    }
+
+    #[test]
+    fn detects_pulses_between_impulses() {
+        // Frequent strong impulses, as produced by a motor controller
+        let samp_rate = 1_024_000;
+        let generator_config = GeneratorConfig {
+            interference: Some(InterferenceConfig { rate: 500.0, duration: 5e-5, amplitude: 20.0 }),
+            ..GeneratorConfig::new(samp_rate, vec![TagConfig::new(100_000.0, 0.9, 0.2, 0.25)], 0.05, 11)
+        };
+        let (samples, truth) = Generator::new(generator_config).generate(5 * samp_rate as usize);
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let target = tag_target(150_100_000.0);
+
+        let mut results = vec![];
+        for &blanking in &[false, true] {
+            let mut builder = DetectorsBuilder::new();
+            if blanking {
+                builder = builder.noise_blanker(BlankerConfig::default());
+            }
+            let mut detectors = builder.build(&sdr_config, slice::from_ref(&target));
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = samples.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+            let evaluation = evaluation::evaluate(&truth, &pulses, samp_rate, 5.0, 0.002);
+            results.push((evaluation, detectors.stats()));
+        }
+
+        let (ref unblanked, _) = results[0];
+        let (ref blanked, stats) = results[1];
+        let stats = stats.blanker;
+        assert!(unblanked.detected < truth.len(), "{:?}", unblanked);
+        assert_eq!(blanked.detected, truth.len(), "{:?}", blanked);
+        assert_eq!(blanked.false_alarms, 0, "{:?}", blanked);
+
+        // Roughly 2.5% of the signal is covered by impulses
+        assert_eq!(stats.samples, 5 * samp_rate);
+        assert!(stats.impulses > 2000, "{:?}", stats);
+        assert!(stats.blanked_fraction() >= 0.02 && stats.blanked_fraction() < 0.05, "{:?}", stats);
+    }
+}
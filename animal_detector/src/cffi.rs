@@ -1,3 +1,4 @@
+use std::f32;
 use std::ptr;
 use std::slice;
 use std::mem;
@@ -23,6 +24,15 @@ pub struct PulseTargetRaw {
     edge_length: i64,
     peak_lookahead: i64,
     gain: f32,
+    cfar_window: f32,
+    cfar_multiplier: f32,
+    period: f32,
+    mortality_period: f32,
+    period_variance: f32,
+    filter_by_period: bool,
+    afc_max_offset: f32,
+    afc_rate: f32,
+    matched_filter: bool,
 }
 
 #[repr(C)]
@@ -43,19 +53,48 @@ impl DetectorConfigRaw {
             amp_enable: false,
             antenna_enable: false,
             baseband_filter: None,
+            sample_format: common::SampleFormat::Cf32,
+            hopping: None,
+            leakage_check: None,
         }
     }
 
     unsafe fn pulse_targets(&self) -> Vec<common::PulseTarget> {
         let targets = slice::from_raw_parts(self.pulse_targets, self.num_targets as usize);
         targets.iter().map(|target| PulseTarget {
+            id: None,
+            name: None,
             freq: target.freq,
             duration: target.duration,
             duration_variance: target.duration_variance,
+            duration_range: None,
             threshold: target.threshold,
+            cfar: if target.cfar_window > 0.0 {
+                Some(common::CfarConfig { window: target.cfar_window, multiplier: target.cfar_multiplier })
+            }
+            else {
+                None
+            },
             edge_length: target.edge_length,
             peak_lookahead: target.peak_lookahead,
             gain: target.gain,
+            period: if target.period > 0.0 { Some(target.period) } else { None },
+            mortality_period: if target.mortality_period > 0.0 { Some(target.mortality_period) } else { None },
+            period_variance: target.period_variance,
+            filter_by_period: target.filter_by_period,
+            afc: if target.afc_max_offset > 0.0 {
+                Some(common::AfcConfig { max_offset: target.afc_max_offset, rate: target.afc_rate })
+            }
+            else {
+                None
+            },
+            algorithm: if target.matched_filter {
+                common::DetectionAlgorithm::MatchedFilter
+            }
+            else {
+                common::DetectionAlgorithm::Edge
+            },
+            shape_limits: None,
         }).collect()
     }
 }
@@ -64,6 +103,9 @@ impl DetectorConfigRaw {
 pub struct PulseRaw {
     freq: f32,
     signal_strength: f32,
+    noise_floor: f32,
+    snr: f32,
+    period: f32,
     gain: f32,
     seconds: u64,
     nanos: u32,
@@ -75,6 +117,12 @@ pub struct PulseList {
     length: u32,
 }
 
+/// Creates a new set of detectors from a raw detector configuration.
+///
+/// # Safety
+///
+/// `config` must point to a valid `DetectorConfigRaw`, with `pulse_targets` pointing to an array
+/// of at least `num_targets` elements.
 #[no_mangle]
 pub unsafe extern "C" fn init_detector(config: *const DetectorConfigRaw) -> *mut Detectors {
     let config = &*config;
@@ -82,13 +130,25 @@ pub unsafe extern "C" fn init_detector(config: *const DetectorConfigRaw) -> *mut
     Box::into_raw(detectors)
 }
 
+/// Frees detectors created with `init_detector`.
+///
+/// # Safety
+///
+/// `detectors` must either be null or a pointer returned by `init_detector` that has not already
+/// been freed.
 #[no_mangle]
 pub unsafe extern "C" fn free_detector(detectors: *mut Detectors) {
     if !detectors.is_null() {
-        Box::from_raw(detectors);
+        drop(Box::from_raw(detectors));
     }
 }
 
+/// Processes the next set of samples (in float32, I/Q format), returning detected pulses.
+///
+/// # Safety
+///
+/// `samples` must either be null or point to at least `length` floats. The returned list must be
+/// released with `free_pulses`.
 #[no_mangle]
 pub unsafe extern "C" fn get_pulses(detectors: &mut Detectors, samples: *const f32, length: u32)
     -> PulseList
@@ -100,13 +160,16 @@ pub unsafe extern "C" fn get_pulses(detectors: &mut Detectors, samples: *const f
     let slice = slice::from_raw_parts(samples, length as usize);
     let pulses = detectors.next_f32(slice);
 
-    if pulses.len() == 0 {
+    if pulses.is_empty() {
         return PulseList { data: ptr::null_mut(), length: 0 };
     }
 
     let mut pulses: Vec<_> = pulses.into_iter().map(|x| PulseRaw {
         freq: x.freq,
         signal_strength: x.signal_strength,
+        noise_floor: x.noise_floor.unwrap_or(f32::NAN),
+        snr: x.snr.unwrap_or(f32::NAN),
+        period: x.period.unwrap_or(f32::NAN),
         gain: x.gain,
         seconds: x.timestamp.seconds,
         nanos: x.timestamp.nanos,
@@ -115,13 +178,18 @@ pub unsafe extern "C" fn get_pulses(detectors: &mut Detectors, samples: *const f
     pulses.shrink_to_fit();
 
     let length = pulses.len();
-    let ptr = pulses.as_mut_ptr();
+    let ptr = pulses.as_mut_slice().as_mut_ptr();
 
     mem::forget(pulses);
 
     PulseList { data: ptr, length: length as u32 }
 }
 
+/// Frees a pulse list returned by `get_pulses`.
+///
+/// # Safety
+///
+/// `pulse_list` must have been returned by `get_pulses` and not already freed.
 #[no_mangle]
 pub unsafe extern "C" fn free_pulses(pulse_list: PulseList) {
     if !pulse_list.data.is_null() {
@@ -0,0 +1,163 @@
+//! An FFT based channelizer that measures every target frequency in a single pass.
+
+use num_complex::Complex;
+
+use dsp_filters::fft::Fft;
+use freq_filter::FreqFilter;
+
+/// The number of channels computed for each bin of an unpadded FFT. Zero padding halves the
+/// spacing between channels, which limits the loss for targets between channels and allows the
+/// side bins used for AFC to be measured exactly.
+const OVERSAMPLE: usize = 2;
+
+/// A filter that computes a zero padded FFT of each window, and measures each target at the
+/// nearest channel. Unlike `FastGoertzel`, the cost of the filter does not depend on the number
+/// of targets, making it faster for large sets of targets.
+pub struct Channelizer {
+    fft: Fft,
+
+    /// The window lookup table
+    window_lut: Vec<f32>,
+
+    /// The samples of the current window, followed by zero padding
+    buffer: Vec<Complex<f32>>,
+
+    /// The channel measured for each target
+    channels: Vec<usize>,
+
+    /// A buffer used to return the result values
+    output_buf: Vec<f32>,
+
+    /// The sample rate of the input signal
+    samp_rate: f32,
+
+    /// The index into the windowing function
+    i: usize,
+}
+
+impl Channelizer {
+    /// Create a new filter for the target frequencies, the length of `window_lut` determines the
+    /// number of samples in each window and must be a power of two.
+    pub fn new<I>(samp_rate: f32, target_freqs: I, window_lut: Vec<f32>) -> Channelizer
+        where I: Iterator<Item = f32>
+    {
+        let size = window_lut.len() * OVERSAMPLE;
+
+        let mut channelizer = Channelizer {
+            fft: Fft::new(size),
+            window_lut,
+            buffer: vec![Complex::new(0.0, 0.0); size],
+            channels: vec![],
+            output_buf: vec![],
+            samp_rate,
+            i: 0,
+        };
+
+        channelizer.channels = target_freqs.map(|x| channelizer.channel(x)).collect();
+        channelizer.output_buf = vec![0.0; channelizer.channels.len()];
+        channelizer
+    }
+
+    /// The nearest channel to `freq`
+    fn channel(&self, freq: f32) -> usize {
+        let size = self.buffer.len() as i64;
+        let channel = (freq / self.samp_rate * size as f32).round() as i64;
+        ((channel % size + size) % size) as usize
+    }
+}
+
+impl FreqFilter for Channelizer {
+    fn window_size(&self) -> usize {
+        self.window_lut.len()
+    }
+
+    fn freq(&self, index: usize) -> f32 {
+        let size = self.buffer.len();
+        let channel = self.channels[index];
+        let channel = if channel >= size / 2 { channel as f32 - size as f32 } else { channel as f32 };
+        channel * self.samp_rate / size as f32
+    }
+
+    fn set_freq(&mut self, index: usize, freq: f32) {
+        self.channels[index] = self.channel(freq);
+    }
+
+    fn add_freq(&mut self, freq: f32) -> usize {
+        let channel = self.channel(freq);
+        self.channels.push(channel);
+        self.output_buf.push(0.0);
+        self.channels.len() - 1
+    }
+
+    fn input(&mut self, real: f32, im: f32) {
+        // Inputs are given in the same order as `FastGoertzel`, which measures the conjugate of
+        // the target frequency.
+        self.buffer[self.i] = Complex::new(im, real) * self.window_lut[self.i];
+        self.i += 1;
+    }
+
+    fn output(&mut self) -> &[f32] {
+        self.i = 0;
+        self.fft.process(&mut self.buffer);
+
+        for (&channel, output) in self.channels.iter().zip(self.output_buf.iter_mut()) {
+            *output = self.buffer[channel].norm();
+        }
+
+        for value in &mut self.buffer {
+            *value = Complex::new(0.0, 0.0);
+        }
+
+        &self.output_buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    use dsp_filters::window::WindowType;
+    use freq_filter::FastGoertzel;
+
+    fn measure<F: FreqFilter>(filter: &mut F, samp_rate: f32, freq: f32) -> Vec<f32> {
+        for n in 0..filter.window_size() {
+            let phase = 2.0 * PI * freq * n as f32 / samp_rate;
+            filter.input(phase.sin(), phase.cos());
+        }
+        filter.output().to_vec()
+    }
+
+    #[test]
+    fn same_power_as_goertzel() {
+        let samp_rate = 1_024_000.0;
+        let targets = [-200_000.0, -1_500.0, 0.0, 100_000.0, 100_500.0, 300_000.0];
+        let window = WindowType::BlackmanHarris.generate_lut(1024);
+
+        let mut goertzel = FastGoertzel::new(samp_rate, targets.iter().cloned(), window.clone());
+        let mut channelizer = Channelizer::new(samp_rate, targets.iter().cloned(), window);
+
+        for &freq in &[100_000.0, -200_000.0, 100_500.0, -1_500.0, 100_250.0] {
+            let expected = measure(&mut goertzel, samp_rate, freq);
+            let actual = measure(&mut channelizer, samp_rate, freq);
+
+            for (&expected, &actual) in expected.iter().zip(&actual) {
+                let error = (expected - actual).abs();
+                assert!(error <= 1e-3 * expected.max(1.0), "{}: {:?} != {:?}", freq, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_channel() {
+        let window = WindowType::BlackmanHarris.generate_lut(1024);
+        let mut channelizer = Channelizer::new(1_024_000.0, [100_200.0, -700.0].iter().cloned(), window);
+
+        // Channels are spaced by half of the bin width
+        assert_eq!(channelizer.freq(0), 100_000.0);
+        assert_eq!(channelizer.freq(1), -500.0);
+
+        channelizer.set_freq(0, 100_300.0);
+        assert_eq!(channelizer.freq(0), 100_500.0);
+    }
+}
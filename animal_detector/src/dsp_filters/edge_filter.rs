@@ -1,4 +1,4 @@
-/// An edge filter that is invariant to the noise floor.
+//! An edge filter that is invariant to the noise floor.
 
 use super::window::{MovingWindow, MovingMean};
 
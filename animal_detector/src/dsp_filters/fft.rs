@@ -0,0 +1,93 @@
+//! An in-place radix-2 fast Fourier transform.
+
+use std::f32::consts::PI;
+
+use num_complex::Complex;
+
+pub struct Fft {
+    /// Precomputed twiddle factors for the largest butterfly
+    twiddles: Vec<Complex<f32>>,
+
+    /// Precomputed bit reversed indices used to reorder the input
+    bit_reverse: Vec<usize>,
+}
+
+impl Fft {
+    /// Create a new FFT of `size` samples, `size` must be a power of two.
+    pub fn new(size: usize) -> Fft {
+        assert!(size.is_power_of_two(), "FFT size must be a power of two, got: {}", size);
+
+        let bits = size.trailing_zeros();
+        let bit_reverse = (0..size)
+            .map(|i| reverse_bits(i, bits))
+            .collect();
+
+        let twiddles = (0..size / 2)
+            .map(|k| Complex::new(0.0, -2.0 * PI * k as f32 / size as f32).exp())
+            .collect();
+
+        Fft { twiddles, bit_reverse }
+    }
+
+    /// The number of samples in each transform
+    pub fn size(&self) -> usize {
+        self.bit_reverse.len()
+    }
+
+    /// Compute the forward transform of `buffer` in place.
+    pub fn process(&self, buffer: &mut [Complex<f32>]) {
+        assert_eq!(buffer.len(), self.size());
+
+        for (i, &j) in self.bit_reverse.iter().enumerate() {
+            if i < j {
+                buffer.swap(i, j);
+            }
+        }
+
+        let size = buffer.len();
+        let mut len = 2;
+        while len <= size {
+            let half = len / 2;
+            let stride = size / len;
+
+            for block in buffer.chunks_mut(len) {
+                let (lower, upper) = block.split_at_mut(half);
+                for (k, (a, b)) in lower.iter_mut().zip(upper.iter_mut()).enumerate() {
+                    let t = self.twiddles[k * stride] * *b;
+                    *b = *a - t;
+                    *a += t;
+                }
+            }
+
+            len *= 2;
+        }
+    }
+}
+
+/// Reverses the order of the lowest `bits` bits of `x`
+fn reverse_bits(x: usize, bits: u32) -> usize {
+    (0..bits).fold(0, |acc, bit| (acc << 1) | ((x >> bit) & 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_dft() {
+        let size = 64;
+        let input: Vec<_> = (0..size)
+            .map(|n| Complex::new((n as f32 * 0.37).sin(), (n as f32 * 1.3).cos() * 0.5))
+            .collect();
+
+        let mut output = input.clone();
+        Fft::new(size).process(&mut output);
+
+        for (k, value) in output.iter().enumerate() {
+            let expected: Complex<f32> = input.iter().enumerate()
+                .map(|(n, &x)| x * Complex::new(0.0, -2.0 * PI * (k * n) as f32 / size as f32).exp())
+                .sum();
+            assert!((value - expected).norm() < 1e-3, "bin {}: {} != {}", k, value, expected);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! A pulse detector that correlates the signal strength with the expected pulse envelope.
+
+use std::collections::VecDeque;
+use std::f32;
+
+use super::noise_floor::NoiseFloor;
+use super::pulse_detector::{DetectPulses, DetectorConfig, Pulse};
+
+/// Detects pulses by correlating the signal strength with a rectangular envelope of the expected
+/// pulse duration (i.e. a moving average). Averaging over the entire pulse makes the detector
+/// more sensitive than edge detection for weak pulses, where the edges are lost in the noise.
+pub struct MatchedFilter {
+    config: DetectorConfig,
+
+    /// The most recent samples, long enough to measure the longest accepted pulse after it has
+    /// been detected
+    history: VecDeque<f32>,
+    history_length: usize,
+
+    /// The noise floor estimator
+    noise_floor: NoiseFloor,
+
+    /// The total number of samples received
+    sample_count: i64,
+
+    /// The number of samples received since the last valid pulse
+    elapsed_samples: i64,
+
+    /// The strongest correlation of the current possible pulse above the noise floor, and the
+    /// index of the last sample of the correlation window
+    peak: Option<(f32, i64)>,
+
+    /// Whether the correlation has fallen below the threshold since the last possible pulse
+    armed: bool,
+
+    /// The index of the last sample of the most recent pulse
+    last_end: i64,
+}
+
+impl MatchedFilter {
+    pub fn new(config: DetectorConfig) -> MatchedFilter {
+        let duration = config.duration.max(1) as usize;
+        let history_length = 2 * (duration + config.duration_variance.max(0) as usize) + 2;
+
+        MatchedFilter {
+            config,
+            history: VecDeque::with_capacity(history_length),
+            history_length,
+            noise_floor: NoiseFloor::new(duration, config.noise_window as usize),
+            sample_count: 0,
+            elapsed_samples: 0,
+            peak: None,
+            armed: true,
+            last_end: -1,
+        }
+    }
+
+    /// The threshold that the correlation must exceed above the noise floor
+    fn threshold(&self) -> f32 {
+        match self.config.cfar_multiplier {
+            // Pulses are never detected until there is an estimate of the noise floor
+            Some(multiplier) => self.noise_floor().map_or(f32::INFINITY, |floor| multiplier * floor),
+            None => self.config.threshold,
+        }
+    }
+
+    /// The mean of the last `duration` samples
+    fn correlation(&self) -> f32 {
+        let duration = (self.config.duration.max(1) as usize).min(self.history.len());
+        let sum: f32 = self.history.iter().rev().take(duration).sum();
+        sum / duration as f32
+    }
+
+    /// Measure the extent of the pulse that produced the peak correlation at `peak_end`, returning
+    /// the index of the first sample of the pulse and its duration. The pulse is taken to be the
+    /// range of samples that are most consistently above half of the pulse level, which is robust
+    /// to individual samples crossing the level in noisy signals.
+    fn measure(&self, peak: f32, peak_end: i64) -> Option<(i64, i64)> {
+        let half_level = self.noise_floor().unwrap_or(0.0) + 0.5 * peak;
+
+        // Search one sample beyond the longest accepted pulse, so that longer pulses are rejected
+        let first_index = self.sample_count - self.history.len() as i64;
+        let variance = self.config.duration_variance.max(0);
+        let lower = (peak_end - self.config.duration - variance).max(first_index);
+        let upper = (peak_end + variance + 1).min(self.sample_count - 1);
+
+        // Find the range with the maximum sum of samples above the level (Kadane's algorithm)
+        let mut best: Option<(f32, i64, i64)> = None;
+        let mut current = (0.0, lower);
+        for i in lower..=upper {
+            let value = self.history[(i - first_index) as usize] - half_level;
+            if current.0 <= 0.0 {
+                current = (value, i);
+            }
+            else {
+                current.0 += value;
+            }
+
+            if current.0 > 0.0 && best.map_or(true, |x| current.0 > x.0) {
+                best = Some((current.0, current.1, i));
+            }
+        }
+
+        best.map(|(_, start, end)| (start, end - start + 1))
+    }
+
+    fn end_of_pulse(&mut self, peak: f32, peak_end: i64) -> Option<Pulse> {
+        self.peak = None;
+        self.armed = false;
+
+        let (start, duration) = self.measure(peak, peak_end)?;
+        if (duration - self.config.duration).abs() > self.config.duration_variance {
+            return None;
+        }
+
+        // Ignore the tail of a pulse that has already been reported
+        if start <= self.last_end {
+            return None;
+        }
+        self.last_end = start + duration - 1;
+
+        let first_index = self.sample_count - self.history.len() as i64;
+        let offset = (start - first_index) as usize;
+        let signal_strength = self.history.iter().skip(offset).take(duration as usize).sum::<f32>()
+            / duration as f32;
+
+        let elapsed_samples = self.elapsed_samples;
+        self.elapsed_samples = 0;
+
+        Some(Pulse {
+            duration,
+            max_signal_strength: signal_strength,
+            mean_signal_strength: signal_strength,
+            elapsed_samples,
+            start_offset: self.sample_count - 1 - start,
+            noise_floor: self.noise_floor(),
+        })
+    }
+}
+
+impl DetectPulses for MatchedFilter {
+    fn config(&self) -> &DetectorConfig {
+        &self.config
+    }
+
+    fn noise_floor(&self) -> Option<f32> {
+        self.noise_floor.floor()
+    }
+
+    fn input(&mut self, sample: f32) -> Option<Pulse> {
+        self.elapsed_samples += 1;
+        self.sample_count += 1;
+
+        self.noise_floor.input(sample);
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+
+        let current = self.sample_count - 1;
+        let value = self.correlation() - self.noise_floor().unwrap_or(0.0);
+        let threshold = self.threshold();
+
+        match self.peak {
+            Some((peak, peak_end)) => {
+                // The pulse has passed once the correlation falls well below the peak, or the
+                // window has moved past the longest accepted pulse
+                let passed = current - peak_end > self.config.duration + self.config.duration_variance;
+                if value < threshold || value < 0.5 * peak || passed {
+                    return self.end_of_pulse(peak, peak_end);
+                }
+                if value > peak {
+                    self.peak = Some((value, current));
+                }
+            }
+            None if value < threshold => self.armed = true,
+            None if self.armed => self.peak = Some((value, current)),
+            None => {}
+        }
+
+        None
+    }
+}
+
+/// Detects pulses with a range of possible durations, using a set of matched filters that each
+/// cover part of the range. Longer filters are more sensitive to long pulses, but reject short
+/// pulses, so the bank is as sensitive as a single matched filter with the correct duration.
+pub struct MatchedFilterBank {
+    /// The configuration of the bank, which accepts any duration in the range
+    config: DetectorConfig,
+    filters: Vec<MatchedFilter>,
+
+    /// The total number of samples received
+    sample_count: i64,
+
+    /// The number of samples received since the last valid pulse
+    elapsed_samples: i64,
+
+    /// The index of the last sample of the most recent pulse, which stops the same pulse being
+    /// reported by more than one filter
+    last_end: i64,
+}
+
+impl MatchedFilterBank {
+    /// Create a bank of filters for pulses between `min_duration` and `max_duration` samples long,
+    /// where `variance` is the allowed variance in duration (in samples) at either end of the
+    /// range. `config` is the configuration for the whole range.
+    pub fn new(config: DetectorConfig, min_duration: i64, max_duration: i64, variance: i64) -> MatchedFilterBank {
+        // Each filter accepts durations within a quarter of its length, and the next filter starts
+        // where the previous filter ends
+        let mut filters = vec![];
+        let mut duration = min_duration.max(1);
+        loop {
+            let duration_variance = (duration / 4).max(variance).max(1);
+            filters.push(MatchedFilter::new(DetectorConfig { duration, duration_variance, ..config }));
+
+            if duration + duration_variance >= max_duration + variance {
+                break;
+            }
+            let next_start = duration + duration_variance + 1;
+            duration = (next_start * 4 / 3).max(next_start + 1);
+        }
+
+        MatchedFilterBank { config, filters, sample_count: 0, elapsed_samples: 0, last_end: -1 }
+    }
+}
+
+impl DetectPulses for MatchedFilterBank {
+    fn config(&self) -> &DetectorConfig {
+        &self.config
+    }
+
+    fn noise_floor(&self) -> Option<f32> {
+        self.filters[0].noise_floor()
+    }
+
+    fn input(&mut self, sample: f32) -> Option<Pulse> {
+        self.elapsed_samples += 1;
+        self.sample_count += 1;
+
+        let mut result = None;
+        for filter in &mut self.filters {
+            let mut pulse = match filter.input(sample) {
+                Some(pulse) => pulse,
+                None => continue,
+            };
+
+            let start = self.sample_count - 1 - pulse.start_offset;
+            let in_range = (pulse.duration - self.config.duration).abs() <= self.config.duration_variance;
+            if result.is_some() || start <= self.last_end || !in_range {
+                continue;
+            }
+            self.last_end = start + pulse.duration - 1;
+
+            pulse.elapsed_samples = self.elapsed_samples;
+            self.elapsed_samples = 0;
+            result = Some(pulse);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use dsp_filters::pulse_detector::PulseDetector;
+
+    fn config(cfar_multiplier: Option<f32>) -> DetectorConfig {
+        DetectorConfig {
+            samp_rate: 1.0,
+            duration: 8,
+            duration_variance: 2,
+            threshold: 0.5,
+            noise_window: 80,
+            cfar_multiplier,
+            edge_length: 3,
+            peak_lookahead: 1,
+        }
+    }
+
+    #[test]
+    fn detects_pulse() {
+        let mut detector = MatchedFilter::new(config(None));
+
+        let mut signal = [0.0; 40];
+        for value in &mut signal[10..18] {
+            *value = 1.0;
+        }
+
+        let pulses: Vec<_> = signal.iter().enumerate()
+            .filter_map(|(i, &x)| detector.input(x).map(|pulse| (i as i64, pulse)))
+            .collect();
+        assert_eq!(pulses.len(), 1);
+
+        let (index, pulse) = pulses[0];
+        assert_eq!(pulse.duration, 8);
+        assert_eq!(index - pulse.start_offset, 10);
+        assert!((pulse.max_signal_strength - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_wrong_duration() {
+        let mut detector = MatchedFilter::new(config(None));
+
+        // Pulses that are too short and too long
+        let signal: Vec<f32> = (0..60)
+            .map(|i| if (i >= 10 && i < 14) || (i >= 30 && i < 50) { 1.0 } else { 0.0 })
+            .collect();
+        assert!(signal.iter().all(|&x| detector.input(x).is_none()));
+    }
+
+    #[test]
+    fn filter_bank_durations() {
+        let config = DetectorConfig { duration: 25, duration_variance: 16, ..config(None) };
+        let mut bank = MatchedFilterBank::new(config, 10, 40, 1);
+
+        // Pulses of 10, 25 and 40 samples are each detected once, a 60 sample pulse is not
+        let mut signal = vec![0.0; 400];
+        for &(start, length) in &[(20, 10), (100, 25), (200, 40), (300, 60)] {
+            signal[start..start + length].iter_mut().for_each(|x| *x = 1.0);
+        }
+
+        let pulses: Vec<_> = signal.iter().enumerate()
+            .filter_map(|(i, &x)| bank.input(x).map(|pulse| (i as i64 - pulse.start_offset, pulse.duration)))
+            .collect();
+        assert_eq!(pulses, vec![(20, 10), (100, 25), (200, 40)]);
+    }
+
+    #[test]
+    fn detects_weak_pulses() {
+        // Noise from a simple linear congruential generator, with weak pulses every 40 samples
+        let mut state = 1_u32;
+        let signal: Vec<f32> = (0..2000)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = (state >> 16) as f32 / 65536.0 * 2.0;
+                if i % 40 >= 20 && i % 40 < 28 { noise + 1.0 } else { noise }
+            })
+            .collect();
+
+        let config = DetectorConfig { threshold: 0.0, ..config(Some(0.5)) };
+        let mut detector = MatchedFilter::new(config);
+        let starts: Vec<_> = signal.iter()
+            .enumerate()
+            .filter_map(|(i, &x)| detector.input(x).map(|pulse| i as i64 - pulse.start_offset))
+            .collect();
+        assert!(starts.iter().all(|x| (x % 40 - 20).abs() <= 4), "{:?}", starts);
+        assert!(starts.windows(2).all(|x| x[0] < x[1]));
+
+        // Edge detection misses most of the pulses
+        let mut edge_detector = PulseDetector::new(config);
+        let edge_pulses = edge_detector.pulses(signal.iter().cloned()).count();
+        assert!(starts.len() >= 2 * edge_pulses, "{} {}", starts.len(), edge_pulses);
+    }
+}
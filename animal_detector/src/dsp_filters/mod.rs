@@ -1,6 +1,9 @@
 //! A implementation of digital signal processing filtering algorithms.
 
 pub mod edge_filter;
+pub mod fft;
+pub mod matched_filter;
+pub mod noise_floor;
 pub mod peak_detector;
 pub mod pulse_detector;
 pub mod window;
@@ -0,0 +1,91 @@
+//! A noise floor estimator used for constant false alarm rate (CFAR) thresholding.
+
+use super::window::MedianWindow;
+
+/// Estimates the noise floor of a signal as the median of the means of consecutive blocks of
+/// samples. Using the median of the blocks ensures that blocks containing pulses are ignored, as
+/// long as pulses are present for less than half of the window.
+pub struct NoiseFloor {
+    /// The number of samples in each block
+    block_length: usize,
+
+    /// The sum of the samples in the current block
+    block_sum: f32,
+
+    /// The number of samples in the current block
+    block_count: usize,
+
+    /// The means of the most recent blocks
+    block_means: MedianWindow,
+
+    /// The number of blocks required before the estimate is valid
+    warm_up: usize,
+
+    /// The current estimate of the noise floor
+    floor: Option<f32>,
+}
+
+impl NoiseFloor {
+    /// Create a new noise floor estimator using blocks of `block_length` samples and a total
+    /// window of `window` samples.
+    pub fn new(block_length: usize, window: usize) -> NoiseFloor {
+        let block_length = block_length.max(1);
+        let num_blocks = (window / block_length).max(3);
+
+        NoiseFloor {
+            block_length,
+            block_sum: 0.0,
+            block_count: 0,
+            block_means: MedianWindow::new(vec![0.0; num_blocks]),
+            warm_up: num_blocks,
+            floor: None,
+        }
+    }
+
+    /// Process a new sample
+    pub fn input(&mut self, value: f32) {
+        self.block_sum += value;
+        self.block_count += 1;
+
+        if self.block_count >= self.block_length {
+            self.block_means.next(self.block_sum / self.block_count as f32);
+            self.block_sum = 0.0;
+            self.block_count = 0;
+
+            if self.warm_up > 0 {
+                self.warm_up -= 1;
+            }
+            if self.warm_up == 0 {
+                self.floor = Some(self.block_means.median());
+            }
+        }
+    }
+
+    /// The current estimate of the noise floor, or `None` if not enough samples have been seen
+    pub fn floor(&self) -> Option<f32> {
+        self.floor
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignores_pulses() {
+        let mut noise_floor = NoiseFloor::new(4, 40);
+
+        for i in 0..36 {
+            noise_floor.input(if i % 3 == 0 { 0.5 } else { 1.5 });
+            assert!(noise_floor.floor().is_none());
+        }
+
+        // A pulse much stronger than the noise covering two blocks
+        for i in 0..44 {
+            noise_floor.input(if i >= 20 && i < 28 { 100.0 } else if i % 3 == 0 { 0.5 } else { 1.5 });
+        }
+
+        let floor = noise_floor.floor().unwrap();
+        assert!(floor > 0.75 && floor < 1.5, "{}", floor);
+    }
+}
@@ -24,13 +24,18 @@ impl PeakDetector {
     /// Configure a new peak detector
     pub fn new(threshold: f32, look_ahead: usize) -> PeakDetector {
         PeakDetector {
-            threshold: threshold,
+            threshold,
             look_ahead: MovingWindow::new(vec![0.0; look_ahead]),
             direction: Direction::Up,
             prev_value: 0.0,
         }
     }
 
+    /// Update the threshold used for detection
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
     pub fn input(&mut self, sample: f32) -> Option<f32> {
         let value = self.look_ahead.next(sample);
 
@@ -1,5 +1,9 @@
+use std::f32;
+
 use super::edge_filter::EdgeFilter;
+use super::noise_floor::NoiseFloor;
 use super::peak_detector::PeakDetector;
+use super::window::MovingWindow;
 
 #[derive(Copy, Clone, Debug)]
 pub struct DetectorConfig {
@@ -12,10 +16,15 @@ pub struct DetectorConfig {
     /// The maximum allowed duration variance (number of samples).
     pub duration_variance: i64,
 
-    /// The pulse threshold.
-    /// TODO: improve threshold selection
+    /// The pulse threshold, used when `cfar_multiplier` is not configured.
     pub threshold: f32,
 
+    /// The number of samples used to estimate the noise floor
+    pub noise_window: i64,
+
+    /// If set, the pulse threshold is this multiple of the current noise floor.
+    pub cfar_multiplier: Option<f32>,
+
     /// The number of samples used for edge detection
     pub edge_length: i64,
 
@@ -28,11 +37,34 @@ pub struct Pulse {
     /// The duration of the pulse (number of samples).
     pub duration: i64,
 
-    /// The maximum signal strength of the pulse.
+    /// The maximum signal strength of the pulse. For edge detection this is the height of the
+    /// strongest edge of the pulse above the signal either side of it.
     pub max_signal_strength: f32,
 
+    /// The mean signal strength over the duration of the pulse.
+    pub mean_signal_strength: f32,
+
     /// The number of samples since the last pulse
     pub elapsed_samples: i64,
+
+    /// The number of samples between the rising edge of the pulse and the sample that completed
+    /// the detection (includes the delay introduced by the edge filter and peak look ahead).
+    pub start_offset: i64,
+
+    /// The estimated noise floor when the pulse was detected.
+    pub noise_floor: Option<f32>,
+}
+
+/// A detector that finds pulses in a stream of signal strength values
+pub trait DetectPulses {
+    /// The configuration of the detector
+    fn config(&self) -> &DetectorConfig;
+
+    /// The current estimate of the noise floor.
+    fn noise_floor(&self) -> Option<f32>;
+
+    /// Process a new sample, returning a pulse if one has been completed
+    fn input(&mut self, sample: f32) -> Option<Pulse>;
 }
 
 pub struct PulseDetector {
@@ -53,27 +85,57 @@ pub struct PulseDetector {
 
     /// The filter used for detecting peaks
     peak_detector: PeakDetector,
+
+    /// The noise floor estimator
+    noise_floor: NoiseFloor,
+
+    /// Delays the input by the delay of the edge filter and peak detector, so that the samples of
+    /// the current pulse can be summed as its edges are found
+    delay_line: MovingWindow<f32>,
+
+    /// The sum of the delayed samples since the start of the current possible pulse
+    pulse_sum: f32,
 }
 
 impl PulseDetector {
     /// Create a new pulse detector with a provided configuration
     pub fn new(config: DetectorConfig) -> PulseDetector {
+        // Pulses are never detected until there is an estimate of the noise floor
+        let threshold = match config.cfar_multiplier {
+            Some(_) => f32::INFINITY,
+            None => config.threshold,
+        };
+
         PulseDetector {
-            config: config,
+            config,
             count: 0,
             elapsed_samples: 0,
             edge_start: 0.0,
             edge_filter: EdgeFilter::new(config.edge_length as usize),
-            peak_detector: PeakDetector::new(config.threshold, config.peak_lookahead as usize),
+            peak_detector: PeakDetector::new(threshold, config.peak_lookahead as usize),
+            noise_floor: NoiseFloor::new(config.duration as usize, config.noise_window as usize),
+            delay_line: MovingWindow::new(vec![0.0; (config.edge_length + config.peak_lookahead) as usize]),
+            pulse_sum: 0.0,
         }
     }
 
+    /// The current estimate of the noise floor.
+    pub fn noise_floor(&self) -> Option<f32> {
+        self.noise_floor.floor()
+    }
+
     /// Process a new sample with the pulse detector
     pub fn input(&mut self, sample: f32) -> Option<Pulse> {
         self.elapsed_samples += 1;
 
+        self.noise_floor.input(sample);
+        if let (Some(multiplier), Some(floor)) = (self.config.cfar_multiplier, self.noise_floor()) {
+            self.peak_detector.set_threshold(multiplier * floor);
+        }
+
         self.edge_filter.input(sample);
         let peak = self.peak_detector.input(self.edge_filter.output());
+        let delayed = self.delay_line.next(sample);
 
         if self.count == 0 {
             if let Some(peak) = peak {
@@ -81,6 +143,7 @@ impl PulseDetector {
                 if peak > 0.0 {
                     self.count = 1;
                     self.edge_start = peak;
+                    self.pulse_sum = delayed;
                 }
             }
         }
@@ -99,9 +162,12 @@ impl PulseDetector {
                         self.elapsed_samples = 0;
 
                         return Some(Pulse {
-                            duration: duration,
+                            duration,
                             max_signal_strength: self.edge_start.max(peak.abs()),
-                            elapsed_samples: elapsed_samples,
+                            mean_signal_strength: self.pulse_sum / duration.max(1) as f32,
+                            elapsed_samples,
+                            start_offset: duration + self.filter_delay(),
+                            noise_floor: self.noise_floor(),
                         });
                     }
                 }
@@ -109,20 +175,27 @@ impl PulseDetector {
                 else if peak > self.edge_start {
                     self.count = 1;
                     self.edge_start = peak;
+                    self.pulse_sum = 0.0;
                 }
             }
+            self.pulse_sum += delayed;
         }
 
         None
     }
 
+    /// The number of samples between an edge in the input and the detection of its peak
+    fn filter_delay(&self) -> i64 {
+        self.config.edge_length + self.config.peak_lookahead
+    }
+
     /// Checks if a negative peak is a match for a positive peak
     fn check_peak(&self, peak: f32) -> bool {
         (self.edge_start + peak).abs() < self.edge_start * 0.5
     }
 
     /// Generate an iterator of pulses given an iterator of signal strength values.
-    pub fn pulses<I: Iterator<Item = f32>>(&mut self, iter: I) -> PulseIterator<I> {
+    pub fn pulses<I: Iterator<Item = f32>>(&mut self, iter: I) -> PulseIterator<'_, I> {
         PulseIterator {
             detector: self,
             inner: iter,
@@ -130,6 +203,20 @@ impl PulseDetector {
     }
 }
 
+impl DetectPulses for PulseDetector {
+    fn config(&self) -> &DetectorConfig {
+        &self.config
+    }
+
+    fn noise_floor(&self) -> Option<f32> {
+        PulseDetector::noise_floor(self)
+    }
+
+    fn input(&mut self, sample: f32) -> Option<Pulse> {
+        PulseDetector::input(self, sample)
+    }
+}
+
 pub struct PulseIterator<'a, I> {
     detector: &'a mut PulseDetector,
     inner: I,
@@ -139,7 +226,7 @@ impl<'a, I: Iterator<Item = f32>> Iterator for PulseIterator<'a, I> {
     type Item = Pulse;
 
     fn next(&mut self) -> Option<Pulse> {
-        while let Some(sample) = self.inner.next() {
+        for sample in self.inner.by_ref() {
             if let Some(pulse) = self.detector.input(sample) {
                 return Some(pulse);
             }
@@ -155,9 +242,12 @@ mod test {
     #[test]
     fn iter_test() {
         let config = DetectorConfig {
+            samp_rate: 1.0,
             duration: 4,
             duration_variance: 1,
             threshold: 0.1,
+            noise_window: 8,
+            cfar_multiplier: None,
             edge_length: 3,
             peak_lookahead: 1,
         };
@@ -172,7 +262,41 @@ mod test {
 
         assert_eq!(pulse.duration, 4);
         assert!((pulse.max_signal_strength - 1.0).abs() < 1.0e-6);
+        assert!((pulse.mean_signal_strength - 1.0).abs() < 1.0e-6);
+
+        // The pulse starts at index 4 and is detected at the last sample (index 12)
+        assert_eq!(pulse.start_offset, 8);
 
         assert!(pulse_iter.next().is_none());
     }
+
+    #[test]
+    fn cfar_gain_invariant() {
+        let config = DetectorConfig {
+            samp_rate: 1.0,
+            duration: 4,
+            duration_variance: 1,
+            threshold: 0.0,
+            noise_window: 40,
+            cfar_multiplier: Some(2.0),
+            edge_length: 3,
+            peak_lookahead: 1,
+        };
+
+        // Noise with a pulse 5x the noise floor every 25 samples
+        let signal: Vec<f32> = (0..200)
+            .map(|i| if i % 25 >= 10 && i % 25 < 14 { 5.0 } else if i % 2 == 0 { 0.8 } else { 1.2 })
+            .collect();
+
+        for &gain in &[1.0e-4, 1.0, 1.0e4] {
+            let mut pulse_detector = PulseDetector::new(config);
+            let pulses: Vec<_> = pulse_detector.pulses(signal.iter().map(|x| x * gain)).collect();
+
+            // The first pulse is missed because it occurs before the noise floor is estimated
+            assert_eq!(pulses.len(), 7);
+            assert!(pulses.iter().all(|x| x.duration == 4 && x.noise_floor.is_some()));
+            assert!(pulses.iter().all(|x| (x.mean_signal_strength / gain - 5.0).abs() < 1.0e-3));
+            assert!((pulse_detector.noise_floor().unwrap() / gain - 1.0).abs() < 0.25);
+        }
+    }
 }
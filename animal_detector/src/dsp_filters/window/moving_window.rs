@@ -11,7 +11,7 @@ impl<T> MovingWindow<T> {
         let mut buffer: VecDeque<T> = VecDeque::new();
         buffer.extend(start);
 
-        MovingWindow { buffer: buffer }
+        MovingWindow { buffer }
     }
 
     /// Add a new element to the moving window, removing and returning the oldest element.
@@ -21,7 +21,7 @@ impl<T> MovingWindow<T> {
     }
 
     /// Returns an iterator over the elements in the window.
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         self.buffer.iter()
     }
 }
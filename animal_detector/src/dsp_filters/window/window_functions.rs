@@ -1,13 +1,68 @@
 use std::f32::{self, consts::PI};
+use std::str::FromStr;
 
 pub type WindowingFunction = fn(i: usize, window_size: usize) -> f32;
 
 /// Generates a lookup table for a windowing function with a set window size
-pub fn generate_lut(window_fn: WindowingFunction, window_size: usize) -> Vec<f32> {
+pub fn generate_lut<F>(window_fn: F, window_size: usize) -> Vec<f32>
+    where F: Fn(usize, usize) -> f32
+{
     let window_norm = 1.0 / (0..window_size).map(|i| window_fn(i, window_size)).sum::<f32>();
     (0..window_size).map(|i| window_fn(i, window_size) * window_norm).collect()
 }
 
+/// A named windowing function
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WindowType {
+    Rectangular,
+    Triangular,
+    BlackmanHarris,
+    BlackmanNuttall,
+    Hann,
+    /// A Kaiser window with the specified beta parameter
+    Kaiser(f32),
+}
+
+impl WindowType {
+    /// Generates a lookup table for this window with a set window size
+    pub fn generate_lut(&self, window_size: usize) -> Vec<f32> {
+        match *self {
+            WindowType::Rectangular => generate_lut(rectangular, window_size),
+            WindowType::Triangular => generate_lut(triangular, window_size),
+            WindowType::BlackmanHarris => generate_lut(blackman_harris, window_size),
+            WindowType::BlackmanNuttall => generate_lut(blackman_nuttal, window_size),
+            WindowType::Hann => generate_lut(hann, window_size),
+            WindowType::Kaiser(beta) => generate_lut(|i, size| kaiser(i, size, beta), window_size),
+        }
+    }
+}
+
+impl FromStr for WindowType {
+    type Err = String;
+
+    /// Parses a window name, e.g. `blackman-harris`, `hann` or `kaiser:8.6`
+    fn from_str(name: &str) -> Result<WindowType, String> {
+        let mut parts = name.splitn(2, ':');
+        let window = match (parts.next().unwrap_or(""), parts.next()) {
+            ("rectangular", None) => WindowType::Rectangular,
+            ("triangular", None) => WindowType::Triangular,
+            ("blackman-harris", None) => WindowType::BlackmanHarris,
+            ("blackman-nuttall", None) => WindowType::BlackmanNuttall,
+            ("hann", None) => WindowType::Hann,
+            ("kaiser", None) => WindowType::Kaiser(DEFAULT_KAISER_BETA),
+            ("kaiser", Some(beta)) => {
+                WindowType::Kaiser(beta.parse().map_err(|e| format!("Invalid beta: {}", e))?)
+            }
+            _ => return Err(format!("Unknown window function: {}", name)),
+        };
+        Ok(window)
+    }
+}
+
+/// The beta value used by a Kaiser window when none is specified (approximately the same sidelobe
+/// level as a Blackman-Harris window).
+pub const DEFAULT_KAISER_BETA: f32 = 8.6;
+
 #[inline]
 pub fn rectangular(_i: usize, _window_size: usize) -> f32 {
     1.0
@@ -30,6 +85,37 @@ pub fn blackman_nuttal(i: usize, window_size: usize) -> f32 {
     generic_blackman_window(i, window_size, (0.3635819, 0.4891775, 0.1365995, 0.0106411))
 }
 
+#[inline]
+pub fn hann(i: usize, window_size: usize) -> f32 {
+    let n = i as f32;
+    let max = window_size as f32 - 1.0;
+    0.5 - 0.5 * (2. * PI * n / max).cos()
+}
+
+#[inline]
+pub fn kaiser(i: usize, window_size: usize, beta: f32) -> f32 {
+    let n = i as f32;
+    let max = window_size as f32 - 1.0;
+    let x = 2.0 * n / max - 1.0;
+    bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(beta)
+}
+
+/// The zeroth order modified Bessel function of the first kind (computed using its power series)
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+
+    for k in 1..50 {
+        term *= (half_x / k as f32) * (half_x / k as f32);
+        sum += term;
+        if term < sum * 1.0e-8 {
+            break;
+        }
+    }
+    sum
+}
+
 #[inline]
 pub fn generic_blackman_window(i: usize, window_size: usize, a: (f32, f32, f32, f32)) -> f32 {
     let n = i as f32;
@@ -40,3 +126,25 @@ pub fn generic_blackman_window(i: usize, window_size: usize, a: (f32, f32, f32,
     a0 - a1 * (2. * PI * n / max).cos() + a2 * (4. * PI * n / max).cos() -
         a3 * (6. * PI * n / max).cos()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_windows() {
+        let windows = ["rectangular", "triangular", "blackman-harris", "blackman-nuttall", "hann", "kaiser"];
+        for name in windows.iter() {
+            let lut = name.parse::<WindowType>().unwrap().generate_lut(64);
+            assert!((lut.iter().sum::<f32>() - 1.0).abs() < 1.0e-5, "{}", name);
+            assert!((lut[10] - lut[53]).abs() < 1.0e-6, "{} is not symmetric", name);
+        }
+
+        // A Kaiser window with beta = 0 is a rectangular window
+        let kaiser = "kaiser:0".parse::<WindowType>().unwrap().generate_lut(64);
+        assert!(kaiser.iter().all(|&x| (x - 1.0 / 64.0).abs() < 1.0e-6));
+
+        assert!("kaiser:x".parse::<WindowType>().is_err());
+        assert!("welch".parse::<WindowType>().is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Measures the distribution of pulse durations for targets with a range of possible durations, so
+//! that the actual pulse width of a tag can be found.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+use common::{DurationRange, DurationStats, PulseTarget, Timestamp};
+
+/// The number of recent pulse durations used to compute the median and suggested duration
+const RECENT_DURATIONS: usize = 100;
+
+/// The number of pulses between reports of the measured durations of targets with a duration range
+const REPORT_INTERVAL: usize = 10;
+
+/// The result of measuring the duration of a pulse
+pub enum DurationUpdate {
+    /// Nothing needs to be reported
+    None,
+
+    /// The distribution of the durations should be reported
+    Report(DurationStats),
+
+    /// The target should be changed to the suggested duration, then the distribution reported
+    Tighten(PulseTarget, DurationStats),
+}
+
+/// A histogram of the durations of the pulses from a single target
+pub struct DurationHistogram {
+    range: DurationRange,
+
+    /// The width (in seconds) of each bin, which is the resolution of the pulse detector
+    bin_width: f32,
+
+    /// The number of pulses in each bin
+    counts: Vec<usize>,
+
+    /// The durations (in seconds) of the most recent pulses
+    recent: VecDeque<f32>,
+
+    /// The total number of pulses recorded
+    count: usize,
+}
+
+impl DurationHistogram {
+    /// Create a histogram covering `range`, where `resolution` is the smallest difference (in
+    /// seconds) in duration that the detector can measure.
+    pub fn new(range: DurationRange, resolution: f32) -> DurationHistogram {
+        let bins = ((range.max - range.min) / resolution).round().max(0.0) as usize + 1;
+        DurationHistogram {
+            range,
+            bin_width: resolution,
+            counts: vec![0; bins],
+            recent: VecDeque::with_capacity(RECENT_DURATIONS),
+            count: 0,
+        }
+    }
+
+    /// Record the duration (in seconds) of a pulse. Durations outside of the range are counted in
+    /// the first or last bin, and durations that are not finite are ignored.
+    pub fn record(&mut self, duration: f32) {
+        if !duration.is_finite() {
+            return;
+        }
+
+        let bin = ((duration - self.range.min) / self.bin_width).round().max(0.0) as usize;
+        let last = self.counts.len() - 1;
+        self.counts[bin.min(last)] += 1;
+
+        if self.recent.len() == RECENT_DURATIONS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(duration);
+        self.count += 1;
+    }
+
+    /// Record the duration (in seconds) of a pulse from `target` (with ID `target_id`) detected at
+    /// `timestamp`. The distribution is reported every few pulses, and once enough pulses have been
+    /// recorded the target is tightened to the suggested duration.
+    pub fn update(&mut self, target_id: usize, target: &PulseTarget, duration: f32, timestamp: Timestamp)
+        -> DurationUpdate
+    {
+        self.record(duration);
+
+        let tighten = self.ready_to_tighten();
+        if !tighten && self.count % REPORT_INTERVAL != 0 {
+            return DurationUpdate::None;
+        }
+        match self.stats(target_id, timestamp) {
+            Some(stats) if tighten => {
+                let (duration, duration_variance) = (stats.suggested_duration, stats.suggested_variance);
+                let target = PulseTarget { duration, duration_variance, duration_range: None, ..target.clone() };
+                DurationUpdate::Tighten(target, stats)
+            }
+            Some(stats) => DurationUpdate::Report(stats),
+            None => DurationUpdate::None,
+        }
+    }
+
+    /// The total number of pulses recorded
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Whether enough pulses have been recorded to replace the range with the suggested duration
+    pub fn ready_to_tighten(&self) -> bool {
+        self.range.tighten_after.map_or(false, |x| self.count >= x.max(1))
+    }
+
+    /// The duration and duration variance (in seconds) that would detect the recent pulses. The
+    /// variance covers the middle 80% of the durations, plus two bins either side to allow for the
+    /// rounding of the edges of the pulse.
+    pub fn suggestion(&self) -> Option<(f32, f32)> {
+        let sorted = self.sorted_recent();
+        if sorted.is_empty() {
+            return None;
+        }
+
+        let (low, high) = (percentile(&sorted, 0.1), percentile(&sorted, 0.9));
+        Some((percentile(&sorted, 0.5), (high - low) / 2.0 + 2.0 * self.bin_width))
+    }
+
+    /// A summary of the distribution for reporting, or `None` if no pulses have been recorded. The
+    /// report is not marked as tightened, which is set once the target has been changed.
+    pub fn stats(&self, target_id: usize, timestamp: Timestamp) -> Option<DurationStats> {
+        let sorted = self.sorted_recent();
+        let (suggested_duration, suggested_variance) = self.suggestion()?;
+
+        Some(DurationStats {
+            target_id,
+            count: self.count,
+            median: percentile(&sorted, 0.5),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            histogram: self.counts.clone(),
+            bin_width: self.bin_width,
+            suggested_duration,
+            suggested_variance,
+            tightened: false,
+            target: None,
+            error: None,
+            timestamp,
+        })
+    }
+
+    fn sorted_recent(&self) -> Vec<f32> {
+        let mut sorted: Vec<f32> = self.recent.iter().cloned().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        sorted
+    }
+}
+
+/// The value at the fraction `p` of the way through `sorted`, which must not be empty
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    sorted[((sorted.len() - 1) as f32 * p).round() as usize]
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32;
+
+    use super::*;
+
+    #[test]
+    fn suggests_measured_duration() {
+        let range = DurationRange { min: 0.01, max: 0.04, tighten_after: Some(20) };
+        let mut histogram = DurationHistogram::new(range, 0.001);
+        assert_eq!(histogram.suggestion(), None);
+
+        // Pulses are measured as 24 or 25 ms, with an occasional outlier
+        for i in 0..20 {
+            assert!(!histogram.ready_to_tighten());
+            histogram.record(match i % 10 { 0 => 0.031, x if x % 2 == 0 => 0.024, _ => 0.025 });
+        }
+        assert!(histogram.ready_to_tighten());
+
+        let (duration, variance) = histogram.suggestion().unwrap();
+        assert!((duration - 0.025).abs() < 1e-6, "{}", duration);
+        assert!(variance > 0.002 && variance < 0.006, "{}", variance);
+
+        let stats = histogram.stats(3, Timestamp { seconds: 0, nanos: 0 }).unwrap();
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.histogram.len(), 31);
+        assert_eq!(stats.histogram[14] + stats.histogram[15] + stats.histogram[21], 20);
+        assert!((stats.max - 0.031).abs() < 1e-6);
+    }
+
+    #[test]
+    fn out_of_range_durations() {
+        let range = DurationRange { min: 0.01, max: 0.02, tighten_after: None };
+        let mut histogram = DurationHistogram::new(range, 0.002);
+        histogram.record(0.005);
+        histogram.record(0.05);
+
+        assert_eq!(histogram.counts, vec![1, 0, 0, 0, 0, 1]);
+        assert!(!histogram.ready_to_tighten());
+    }
+
+    #[test]
+    fn median_matches_suggestion() {
+        let range = DurationRange { min: 0.01, max: 0.04, tighten_after: None };
+        let mut histogram = DurationHistogram::new(range, 0.001);
+        for &duration in &[0.02, f32::NAN, 0.03, 0.025, f32::INFINITY, 0.035] {
+            histogram.record(duration);
+        }
+        assert_eq!(histogram.count(), 4);
+
+        let stats = histogram.stats(0, Timestamp { seconds: 0, nanos: 0 }).unwrap();
+        assert_eq!(stats.median, stats.suggested_duration);
+        assert_eq!(stats.median, 0.03);
+    }
+}
@@ -0,0 +1,148 @@
+//! Measures the performance of the detectors by comparing detected pulses to a known set of
+//! pulses.
+
+use std::cmp::Ordering;
+
+use common::{Pulse, Timestamp};
+use signal_gen::TruePulse;
+
+/// The detection statistics for a signal
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// The length (in seconds) of the signal
+    pub signal_duration: f32,
+
+    /// The number of pulses in the signal
+    pub true_pulses: usize,
+
+    /// The number of pulses in the signal that were detected
+    pub detected: usize,
+
+    /// The number of detections that did not match a pulse in the signal
+    pub false_alarms: usize,
+
+    /// The mean absolute error (in seconds) of the start time of detected pulses
+    pub timing_error: f32,
+
+    /// The mean absolute error (in seconds) of the duration of detected pulses
+    pub duration_error: f32,
+}
+
+impl Evaluation {
+    /// The fraction of pulses that were detected
+    pub fn detection_probability(&self) -> f32 {
+        if self.true_pulses == 0 {
+            return 0.0;
+        }
+        self.detected as f32 / self.true_pulses as f32
+    }
+
+    /// The average number of false alarms per minute of signal
+    pub fn false_alarm_rate(&self) -> f32 {
+        if self.signal_duration <= 0.0 {
+            return 0.0;
+        }
+        self.false_alarms as f32 / (self.signal_duration / 60.0)
+    }
+}
+
+/// Matches the pulses detected in a signal to the pulses known to be in the signal. A detection
+/// matches a pulse if it is for the same target and starts within `tolerance` seconds of the
+/// pulse. Pulse timestamps are taken to be relative to the first sample of the signal.
+pub fn evaluate(
+    truth: &[TruePulse],
+    pulses: &[Pulse],
+    samp_rate: u64,
+    signal_duration: f32,
+    tolerance: f32,
+) -> Evaluation {
+    let samp_rate = samp_rate as f64;
+    let mut matched = vec![false; truth.len()];
+
+    let mut evaluation = Evaluation {
+        signal_duration,
+        true_pulses: truth.len(),
+        ..Evaluation::default()
+    };
+    let mut total_timing_error = 0.0;
+    let mut total_duration_error = 0.0;
+
+    for pulse in pulses {
+        let time = timestamp_seconds(pulse.timestamp);
+
+        // Find the closest unmatched pulse from the same target
+        let closest = truth.iter()
+            .enumerate()
+            .filter(|&(i, x)| !matched[i] && x.tag == pulse.target_id)
+            .map(|(i, x)| (i, x, time - x.start as f64 / samp_rate))
+            .filter(|x| x.2.abs() <= tolerance as f64)
+            .min_by(|a, b| a.2.abs().partial_cmp(&b.2.abs()).unwrap_or(Ordering::Equal));
+
+        match closest {
+            Some((i, expected, error)) => {
+                matched[i] = true;
+                evaluation.detected += 1;
+                total_timing_error += error.abs();
+                total_duration_error += (pulse.duration as f64 - expected.length as f64 / samp_rate).abs();
+            }
+            None => evaluation.false_alarms += 1,
+        }
+    }
+
+    if evaluation.detected > 0 {
+        evaluation.timing_error = (total_timing_error / evaluation.detected as f64) as f32;
+        evaluation.duration_error = (total_duration_error / evaluation.detected as f64) as f32;
+    }
+
+    evaluation
+}
+
+fn timestamp_seconds(timestamp: Timestamp) -> f64 {
+    timestamp.seconds as f64 + timestamp.nanos as f64 * 1e-9
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pulse(target_id: usize, millis: u32, duration: f32) -> Pulse {
+        Pulse {
+            target_id,
+            freq: 0.0,
+            duration,
+            signal_strength: 1.0,
+            noise_floor: None,
+            snr: None,
+            period: None,
+            gain: 0.0,
+            timestamp: Timestamp { seconds: 0, nanos: millis * 1_000_000 },
+            flag: None,
+            slot: None,
+            shape: None,
+        }
+    }
+
+    #[test]
+    fn match_detections() {
+        let true_pulse = |tag, start| TruePulse { tag, start, length: 200, freq: 0.0, amplitude: 1.0 };
+        let truth = [true_pulse(0, 1_000), true_pulse(1, 1_000), true_pulse(0, 5_000), true_pulse(0, 9_000)];
+
+        let pulses = [
+            pulse(0, 102, 0.02),
+            // A second detection of the same pulse
+            pulse(0, 103, 0.02),
+            pulse(1, 98, 0.03),
+            // Too far from the pulse at 500 ms
+            pulse(0, 520, 0.02),
+        ];
+
+        let evaluation = evaluate(&truth, &pulses, 10_000, 30.0, 0.005);
+        assert_eq!(evaluation.true_pulses, 4);
+        assert_eq!(evaluation.detected, 2);
+        assert_eq!(evaluation.false_alarms, 2);
+        assert_eq!(evaluation.detection_probability(), 0.5);
+        assert_eq!(evaluation.false_alarm_rate(), 4.0);
+        assert!((evaluation.timing_error - 0.002).abs() < 1e-6);
+        assert!((evaluation.duration_error - 0.005).abs() < 1e-6);
+    }
+}
@@ -1,10 +1,33 @@
 use std::f32::consts::PI;
 
-use dsp_filters::window;
 use num_complex::Complex;
 
-// TODO: Consider making the number of samples in a window configurable
-pub const SAMPLES_IN_WINDOW: usize = 1024;
+/// The default number of samples in each window of the filter
+pub const DEFAULT_WINDOW_SIZE: usize = 1024;
+
+/// A filter that measures the magnitude of a set of target frequencies over windows of samples.
+pub trait FreqFilter {
+    /// The number of samples in each window
+    fn window_size(&self) -> usize;
+
+    /// The frequency that is measured for the target at `index`. This can differ from the
+    /// requested frequency if the filter only supports a fixed set of frequencies.
+    fn freq(&self, index: usize) -> f32;
+
+    /// Change the frequency of the target at `index`. This should only be called directly after
+    /// `output`, otherwise the result for the current window will be invalid.
+    fn set_freq(&mut self, index: usize, freq: f32);
+
+    /// Add a new target frequency, returning its index. As for `set_freq`, this should only be
+    /// called directly after `output`.
+    fn add_freq(&mut self, freq: f32) -> usize;
+
+    /// Process the next input sample of a complex signal.
+    fn input(&mut self, real: f32, im: f32);
+
+    /// Return the output of the filter, clearing the filter for futher use.
+    fn output(&mut self) -> &[f32];
+}
 
 /// A complex Goertzel filter implementation that supports multiple frequencies.
 pub struct FastGoertzel {
@@ -23,26 +46,58 @@ pub struct FastGoertzel {
     /// The window lookup table
     window_lut: Vec<f32>,
 
+    /// The sample rate of the input signal
+    samp_rate: f32,
+
     /// The index into the windowing function
     i: usize,
 }
 
 impl FastGoertzel {
-    pub fn new<I: Iterator<Item = f32>>(samp_rate: f32, target_freqs: I) -> FastGoertzel {
+    /// Create a new filter for the target frequencies, the length of `window_lut` determines the
+    /// number of samples in each window.
+    pub fn new<I>(samp_rate: f32, target_freqs: I, window_lut: Vec<f32>) -> FastGoertzel
+        where I: Iterator<Item = f32>
+    {
         let targets: Vec<_> = target_freqs.map(|x| 2. * PI * x / samp_rate).collect();
 
         FastGoertzel {
             output_buf: vec![0.0; targets.len()],
             prev: vec![[Complex::new(0., 0.), Complex::new(0., 0.)]; targets.len()],
             coeffs: targets.iter().map(|x| 2. * x.cos()).collect(),
-            targets: targets,
-            window_lut: window::generate_lut(window::blackman_harris, SAMPLES_IN_WINDOW),
+            targets,
+            window_lut,
+            samp_rate,
             i: 0,
         }
     }
+}
 
-    /// Process the next input sample of a complex signal.
-    pub fn input(&mut self, real: f32, im: f32) {
+impl FreqFilter for FastGoertzel {
+    fn window_size(&self) -> usize {
+        self.window_lut.len()
+    }
+
+    fn freq(&self, index: usize) -> f32 {
+        self.targets[index] * self.samp_rate / (2. * PI)
+    }
+
+    fn set_freq(&mut self, index: usize, freq: f32) {
+        let target = 2. * PI * freq / self.samp_rate;
+        self.targets[index] = target;
+        self.coeffs[index] = 2. * target.cos();
+    }
+
+    fn add_freq(&mut self, freq: f32) -> usize {
+        let target = 2. * PI * freq / self.samp_rate;
+        self.targets.push(target);
+        self.coeffs.push(2. * target.cos());
+        self.prev.push([Complex::new(0., 0.), Complex::new(0., 0.)]);
+        self.output_buf.push(0.0);
+        self.targets.len() - 1
+    }
+
+    fn input(&mut self, real: f32, im: f32) {
         let sample = Complex::new(real, im) * self.window_lut[self.i];
 
         for (prev, &coeff) in self.prev.iter_mut().zip(&self.coeffs) {
@@ -54,8 +109,7 @@ impl FastGoertzel {
         self.i += 1;
     }
 
-    /// Return the output of the filter, clearing the filter for futher use.
-    pub fn output(&mut self) -> &[f32] {
+    fn output(&mut self) -> &[f32] {
         self.i = 0;
 
         for ((prev, &target), output) in self.prev
@@ -74,3 +128,115 @@ impl FastGoertzel {
         &self.output_buf
     }
 }
+
+/// Runs several copies of a filter over overlapping windows, producing an output every `hop`
+/// samples instead of every window. Each copy starts its window `hop` samples after the previous
+/// one, so the cost of the filter grows in proportion to the number of copies.
+pub struct OverlappedFilter {
+    filters: Vec<Box<dyn FreqFilter>>,
+
+    /// The number of samples between consecutive outputs
+    hop: usize,
+
+    /// The number of samples since the last output
+    i: usize,
+
+    /// The index of the filter that completes its window next
+    next: usize,
+
+    /// The requested frequency of each target
+    freqs: Vec<f32>,
+
+    /// The frequencies of each filter that need to be updated once its current window completes
+    pending: Vec<Vec<bool>>,
+
+    /// A buffer used to return the result values
+    output_buf: Vec<f32>,
+}
+
+impl OverlappedFilter {
+    /// Create a filter from `filters`, which must be identical and have a window size that is a
+    /// multiple of the number of filters.
+    pub fn new(mut filters: Vec<Box<dyn FreqFilter>>, freqs: Vec<f32>) -> OverlappedFilter {
+        let window_size = filters[0].window_size();
+        let hop = window_size / filters.len();
+
+        // Offset the start of each window by feeding leading zeros, so the first filter completes
+        // after `hop` samples and the others follow at intervals of `hop` samples.
+        for (j, filter) in filters.iter_mut().enumerate() {
+            for _ in 0..window_size - (j + 1) * hop {
+                filter.input(0.0, 0.0);
+            }
+        }
+
+        let pending = vec![vec![false; freqs.len()]; filters.len()];
+        let output_buf = vec![0.0; freqs.len()];
+        OverlappedFilter { filters, hop, i: 0, next: 0, freqs, pending, output_buf }
+    }
+
+    /// The index of the filter that completed the most recent window
+    fn current(&self) -> usize {
+        (self.next + self.filters.len() - 1) % self.filters.len()
+    }
+}
+
+impl FreqFilter for OverlappedFilter {
+    fn window_size(&self) -> usize {
+        self.hop
+    }
+
+    fn freq(&self, index: usize) -> f32 {
+        self.filters[self.current()].freq(index)
+    }
+
+    fn set_freq(&mut self, index: usize, freq: f32) {
+        // Only the filter that has just completed a window can be updated immediately, the others
+        // are updated when their current window completes.
+        let current = self.current();
+        self.freqs[index] = freq;
+        for (j, (filter, pending)) in self.filters.iter_mut().zip(&mut self.pending).enumerate() {
+            if j == current {
+                filter.set_freq(index, freq);
+            }
+            else {
+                pending[index] = true;
+            }
+        }
+    }
+
+    fn add_freq(&mut self, freq: f32) -> usize {
+        for filter in &mut self.filters {
+            filter.add_freq(freq);
+        }
+        for pending in &mut self.pending {
+            pending.push(false);
+        }
+        self.freqs.push(freq);
+        self.output_buf.push(0.0);
+        self.freqs.len() - 1
+    }
+
+    fn input(&mut self, real: f32, im: f32) {
+        for filter in &mut self.filters {
+            filter.input(real, im);
+        }
+        self.i += 1;
+    }
+
+    fn output(&mut self) -> &[f32] {
+        self.i = 0;
+        let current = self.next;
+        self.next = (self.next + 1) % self.filters.len();
+
+        self.output_buf.copy_from_slice(self.filters[current].output());
+
+        for (index, pending) in self.pending[current].iter_mut().enumerate() {
+            if *pending {
+                self.filters[current].set_freq(index, self.freqs[index]);
+                *pending = false;
+            }
+        }
+
+        &self.output_buf
+    }
+}
@@ -0,0 +1,220 @@
+//! Corrects the DC offset and I/Q imbalance of the receiver (e.g. the strong DC spike of the
+//! HackRF), which otherwise mask tags close to the center frequency and produce images of strong
+//! tags mirrored around the center frequency.
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct IqCorrectionConfig {
+    /// The time constant (in seconds) of the high-pass filter that removes the DC offset. Longer
+    /// windows remove less of the signal close to the center frequency, but take longer to settle.
+    pub dc_window: f32,
+
+    /// The time constant (in seconds) used to estimate the gain and phase imbalance
+    pub imbalance_window: f32,
+}
+
+impl Default for IqCorrectionConfig {
+    fn default() -> IqCorrectionConfig {
+        IqCorrectionConfig { dc_window: 0.1, imbalance_window: 1.0 }
+    }
+}
+
+/// The corrections currently applied to the signal
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct IqEstimate {
+    /// The DC offset of the I and Q channels
+    pub dc_offset: (f32, f32),
+
+    /// The gain of the Q channel relative to the I channel (in dB)
+    pub gain_imbalance: f32,
+
+    /// The deviation of the Q channel from quadrature (in degrees)
+    pub phase_imbalance: f32,
+}
+
+/// Removes the DC offset from each channel with a single-pole high-pass filter, then blindly
+/// estimates the gain and phase imbalance from the average power of each channel and their
+/// correlation (which are equal and zero for a balanced receiver), and orthogonalises the Q
+/// channel against the I channel.
+pub struct IqCorrection {
+    dc_alpha: f64,
+    imbalance_alpha: f64,
+
+    /// The number of samples used for each estimate, so that the initial estimates are the mean
+    /// of all samples until the windows are filled
+    count: f64,
+
+    dc_i: f64,
+    dc_q: f64,
+
+    /// The average of I², Q² and I·Q after the DC offset has been removed
+    power_i: f64,
+    power_q: f64,
+    cross: f64,
+
+    /// The current correction, Q' = q_gain·Q + i_gain·I
+    q_gain: f32,
+    i_gain: f32,
+}
+
+impl IqCorrection {
+    pub fn new(config: IqCorrectionConfig, samp_rate: f32) -> IqCorrection {
+        IqCorrection {
+            dc_alpha: 1.0 / (config.dc_window as f64 * samp_rate as f64).max(1.0),
+            imbalance_alpha: 1.0 / (config.imbalance_window as f64 * samp_rate as f64).max(1.0),
+            count: 0.0,
+            dc_i: 0.0,
+            dc_q: 0.0,
+            power_i: 0.0,
+            power_q: 0.0,
+            cross: 0.0,
+            q_gain: 1.0,
+            i_gain: 0.0,
+        }
+    }
+
+    /// The current estimate of the receiver's DC offset and imbalance
+    pub fn estimate(&self) -> IqEstimate {
+        let (gain, sin_phase) = self.imbalance();
+        IqEstimate {
+            dc_offset: (self.dc_i as f32, self.dc_q as f32),
+            gain_imbalance: 20.0 * gain.log10() as f32,
+            phase_imbalance: sin_phase.asin().to_degrees() as f32,
+        }
+    }
+
+    /// The gain of Q relative to I, and the sine of the phase error of Q
+    fn imbalance(&self) -> (f64, f64) {
+        if self.power_i <= 0.0 || self.power_q <= 0.0 {
+            return (1.0, 0.0);
+        }
+        let gain = (self.power_q / self.power_i).sqrt();
+        let sin_phase = (self.cross / (self.power_i * self.power_q).sqrt()).max(-0.99).min(0.99);
+        (gain, sin_phase)
+    }
+
+    /// Correct the next sample
+    pub fn input(&mut self, i: f32, q: f32) -> (f32, f32) {
+        self.count += 1.0;
+        let dc_alpha = self.dc_alpha.max(1.0 / self.count);
+        let imbalance_alpha = self.imbalance_alpha.max(1.0 / self.count);
+
+        self.dc_i += dc_alpha * (i as f64 - self.dc_i);
+        self.dc_q += dc_alpha * (q as f64 - self.dc_q);
+        let i = i as f64 - self.dc_i;
+        let q = q as f64 - self.dc_q;
+
+        self.power_i += imbalance_alpha * (i * i - self.power_i);
+        self.power_q += imbalance_alpha * (q * q - self.power_q);
+        self.cross += imbalance_alpha * (i * q - self.cross);
+
+        // With Q = g·sin(θ + φ), the corrected Q is (Q/g - I·sin φ) / cos φ = sin θ. The correction
+        // is only updated periodically, since the estimates change slowly.
+        if self.count as u64 % 1024 == 0 {
+            let (gain, sin_phase) = self.imbalance();
+            let cos_phase = (1.0 - sin_phase * sin_phase).sqrt();
+            self.q_gain = (1.0 / (gain * cos_phase)) as f32;
+            self.i_gain = (-sin_phase / cos_phase) as f32;
+        }
+
+        (i as f32, self.q_gain * q as f32 + self.i_gain * i as f32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    use common::{SdrConfig, Timestamp};
+    use evaluation;
+    use signal_gen::{tag_target, Generator, GeneratorConfig, TagConfig};
+    use DetectorsBuilder;
+
+    /// The magnitude of the component of the signal at `freq`
+    fn magnitude(samples: &[(f32, f32)], freq: f32) -> f32 {
+        let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |acc, (n, &(i, q))| {
+            let phase = -2.0 * PI * freq * n as f32;
+            (acc.0 + i * phase.cos() - q * phase.sin(), acc.1 + i * phase.sin() + q * phase.cos())
+        });
+        (re * re + im * im).sqrt() / samples.len() as f32
+    }
+
+    #[test]
+    fn corrects_dc_and_imbalance() {
+        let config = IqCorrectionConfig { dc_window: 1000.0, imbalance_window: 5000.0 };
+        let mut correction = IqCorrection::new(config, 1.0);
+
+        // A tone at 0.1 cycles/sample, received with 2 dB of gain imbalance, 8° of phase error and
+        // a DC offset
+        let gain = 10.0_f32.powf(2.0 / 20.0);
+        let phase_error = 8.0_f32.to_radians();
+        let output: Vec<_> = (0..100_000)
+            .map(|n| {
+                let phase = 2.0 * PI * 0.1 * n as f32;
+                correction.input(phase.cos() + 0.3, gain * (phase + phase_error).sin() - 0.2)
+            })
+            .collect();
+
+        let estimate = correction.estimate();
+        assert!((estimate.dc_offset.0 - 0.3).abs() < 0.01, "{:?}", estimate);
+        assert!((estimate.dc_offset.1 + 0.2).abs() < 0.01, "{:?}", estimate);
+        assert!((estimate.gain_imbalance - 2.0).abs() < 0.1, "{:?}", estimate);
+        assert!((estimate.phase_imbalance - 8.0).abs() < 0.2, "{:?}", estimate);
+
+        // The image and the DC offset are removed once the estimates have settled
+        let settled = &output[50_000..];
+        assert!((magnitude(settled, 0.1) - 1.0).abs() < 0.01);
+        assert!(magnitude(settled, -0.1) < 0.005, "{}", magnitude(settled, -0.1));
+        assert!(magnitude(settled, 0.0) < 0.005, "{}", magnitude(settled, 0.0));
+    }
+
+    #[test]
+    fn detects_pulses_near_dc_and_image() {
+        let samp_rate = 1_024_000;
+        let tags = vec![TagConfig::new(600.0, 0.9, 0.5, 0.25), TagConfig::new(150_000.0, 0.9, 2.0, 0.6)];
+        let generator_config = GeneratorConfig::new(samp_rate, tags, 0.05, 9);
+        let (mut samples, truth) = Generator::new(generator_config).generate(10 * samp_rate as usize);
+
+        // A receiver with a large DC offset, 3 dB of gain imbalance and 10° of phase error
+        let gain = 10.0_f32.powf(3.0 / 20.0);
+        let (sin_phase, cos_phase) = 10.0_f32.to_radians().sin_cos();
+        for x in samples.chunks_mut(2) {
+            let (i, q) = (x[0], x[1]);
+            x[0] = i + 1.0;
+            x[1] = gain * (q * cos_phase + i * sin_phase) - 0.5;
+        }
+
+        // The last target is at the image of the strong tag
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let targets = [tag_target(150_000_600.0), tag_target(150_150_000.0), tag_target(149_850_000.0)];
+
+        let mut results = vec![];
+        for &correction in &[false, true] {
+            let mut builder = DetectorsBuilder::new();
+            if correction {
+                builder = builder.iq_correction(IqCorrectionConfig::default());
+            }
+            let mut detectors = builder.build(&sdr_config, &targets);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = samples.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+            let evaluation = evaluation::evaluate(&truth, &pulses, samp_rate, 10.0, 0.002);
+            results.push((evaluation, detectors.iq_estimate()));
+        }
+
+        let (ref uncorrected, _) = results[0];
+        let (ref corrected, estimate) = results[1];
+        // Without correction the tag close to the DC offset is missed, and the image of the strong
+        // tag is detected
+        assert!(uncorrected.detected < truth.len(), "{:?}", uncorrected);
+        assert!(uncorrected.false_alarms > 0, "{:?}", uncorrected);
+        assert_eq!(corrected.detected, truth.len(), "{:?}", corrected);
+        assert_eq!(corrected.false_alarms, 0, "{:?}", corrected);
+
+        let estimate = estimate.unwrap();
+        assert!((estimate.dc_offset.0 - 1.0).abs() < 0.05, "{:?}", estimate);
+        assert!((estimate.dc_offset.1 + 0.5).abs() < 0.05, "{:?}", estimate);
+        assert!((estimate.gain_imbalance - 3.0).abs() < 0.5, "{:?}", estimate);
+        assert!((estimate.phase_imbalance - 10.0).abs() < 2.0, "{:?}", estimate);
+    }
+}
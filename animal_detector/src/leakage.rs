@@ -0,0 +1,36 @@
+//! Identifies pulses that are leakage of a stronger pulse from a target with a nearby frequency,
+//! which is picked up by the sidelobes (or main lobe) of the window used by the frequency filter.
+
+use std::f32::consts::PI;
+
+pub use common::LeakageConfig;
+
+/// The response of a filter using `window` (normalized to a sum of 1) to a tone `freq` cycles per
+/// sample from the center of the filter, relative to its response to a tone at the center.
+pub fn window_response(window: &[f32], freq: f32) -> f32 {
+    let (re, im) = window.iter().enumerate().fold((0.0, 0.0), |acc, (n, &w)| {
+        let (sin, cos) = (2.0 * PI * freq * n as f32).sin_cos();
+        (acc.0 + w * cos, acc.1 + w * sin)
+    });
+    (re * re + im * im).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use dsp_filters::window::WindowType;
+
+    #[test]
+    fn blackman_harris_response() {
+        let window = WindowType::BlackmanHarris.generate_lut(1024);
+        let bin = 1.0 / 1024.0;
+
+        assert!((window_response(&window, 0.0) - 1.0).abs() < 1e-4);
+
+        // The main lobe is 4 bins wide, and the sidelobes are at least 92 dB down
+        let response_db = |bins: f32| 20.0 * window_response(&window, bins * bin).log10();
+        assert!((response_db(1.0) + 3.0).abs() < 1.0, "{}", response_db(1.0));
+        assert!(response_db(3.0) < -30.0, "{}", response_db(3.0));
+        assert!((4..20).all(|x| response_db(x as f32 + 0.5) < -90.0));
+    }
+}
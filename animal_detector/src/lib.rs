@@ -2,108 +2,797 @@
 
 extern crate common;
 extern crate num_complex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
+pub mod afc;
+pub mod blanker;
 pub mod cffi;
+pub mod channelizer;
 pub mod dsp_filters;
+pub mod duration_stats;
+pub mod evaluation;
 pub mod freq_filter;
+pub mod iq_correction;
+pub mod leakage;
+pub mod pulse_history;
+pub mod pulse_shape;
+pub mod sample_format;
+pub mod sigmf;
+pub mod scanner;
+pub mod signal_gen;
+pub mod util;
 
-mod util;
+mod pulse_checks;
 
-use std::cmp;
+use std::{cmp, fmt, i64, mem};
+use std::error::Error;
 
-use common::{SdrConfig, PulseTarget, Pulse, Timestamp};
-use dsp_filters::pulse_detector::{PulseDetector, DetectorConfig};
+use common::{
+    DetectionAlgorithm, DurationStats, SdrConfig, PulseTarget, Pulse, RejectedTarget,
+    RejectionReason, TagStatus, TargetStatus, Timestamp,
+};
+use afc::Afc;
+use blanker::{BlankerConfig, BlankerStats, NoiseBlanker};
+use iq_correction::{IqCorrection, IqCorrectionConfig, IqEstimate};
+use leakage::LeakageConfig;
+use dsp_filters::matched_filter::{MatchedFilter, MatchedFilterBank};
+use duration_stats::{DurationHistogram, DurationUpdate};
+use dsp_filters::pulse_detector::{DetectPulses, PulseDetector, DetectorConfig};
+use dsp_filters::window::WindowType;
+use pulse_history::{PulseHistory, PeriodConfig};
+use pulse_checks::{Candidate, PulseChecks};
+use pulse_shape::Envelope;
+use sample_format::SampleFormat;
 
-use freq_filter::{FastGoertzel, SAMPLES_IN_WINDOW};
+use channelizer::Channelizer;
+use freq_filter::{FastGoertzel, FreqFilter, OverlappedFilter, DEFAULT_WINDOW_SIZE};
+
+/// The length of time (in seconds) used to estimate the noise floor of targets without a CFAR
+/// configuration
+const DEFAULT_NOISE_WINDOW: f32 = 2.0;
+
+/// The default maximum number of filter bins to compute using `FastGoertzel`, above this the
+/// `Channelizer` is used instead.
+pub const DEFAULT_CHANNELIZER_THRESHOLD: usize = 12;
 
 struct Detector {
+    /// The ID of the target, which is used in the pulses and status changes of the target
+    id: usize,
     target: PulseTarget,
-    pulse_detector: PulseDetector,
+    pulse_detector: Box<dyn DetectPulses>,
+    history: PulseHistory,
+    status: TagStatus,
+
+    /// The frequency of the target relative to the center frequency of the SDR
+    baseband_freq: f32,
+
+    /// Frequency tracking for the target, if enabled
+    afc: Option<Afc>,
+
+    /// The recent signal strength of the target, for measuring the shape of its pulses
+    envelope: Envelope,
+
+    /// The measured pulse durations, if the target has a duration range
+    durations: Option<DurationHistogram>,
+
+    /// The index of the target's bin in the filter output
+    bin: usize,
+
+    /// The indices of the two side bins used for AFC in the filter output
+    afc_bins: [usize; 2],
+}
+
+/// The configuration of the pulse detector for a target, given the rate at which the filter
+/// produces outputs
+fn detector_config(samp_rate: f32, window_size: usize, overlap: usize, target: &PulseTarget) -> DetectorConfig {
+    // The filter produces an output every `window_size / overlap` samples. The edge detection
+    // parameters are given in windows, so they are scaled to cover the same length of time.
+    let detector_samp_rate = samp_rate * overlap as f32 / window_size as f32;
+    let overlap = overlap as i64;
+    let noise_window = target.cfar.map_or(DEFAULT_NOISE_WINDOW, |cfar| cfar.window);
+
+    // With a duration range, any duration between the ends of the range (plus the variance) is
+    // accepted
+    let (duration, duration_variance) = match target.duration_range {
+        Some(range) => ((range.min + range.max) / 2.0, (range.max - range.min) / 2.0 + target.duration_variance),
+        None => (target.duration, target.duration_variance),
+    };
+
+    DetectorConfig {
+        samp_rate: detector_samp_rate,
+        duration: (duration * detector_samp_rate) as i64,
+        duration_variance: (duration_variance * detector_samp_rate) as i64,
+        threshold: target.threshold,
+        noise_window: (noise_window * detector_samp_rate) as i64,
+        cfar_multiplier: target.cfar.map(|cfar| cfar.multiplier),
+        edge_length: target.edge_length * overlap,
+        peak_lookahead: target.peak_lookahead * overlap,
+    }
+}
+
+/// The number of windows that pulses must be held for before they can be compared with the
+/// pulses detected by other targets. The same signal is detected by each target after a delay
+/// that depends on the configuration of the target.
+fn release_delay<'a, I>(samp_rate: f32, window_size: usize, overlap: usize, targets: I) -> usize
+    where I: Iterator<Item = &'a PulseTarget>
+{
+    let (min, max) = targets
+        .map(|target| {
+            let config = detector_config(samp_rate, window_size, overlap, target);
+            let delay = config.duration + config.edge_length + config.peak_lookahead;
+            (delay - config.duration_variance, delay + config.duration_variance)
+        })
+        .fold((i64::MAX, 0), |acc, x| (acc.0.min(x.0), acc.1.max(x.1)));
+    (max - min.min(max)) as usize + overlap
+}
+
+/// Whether the frequency of `target` is within the bandwidth of the SDR
+fn in_band(samp_rate: f32, center_freq: f32, target: &PulseTarget) -> bool {
+    (target.freq - center_freq).abs() < samp_rate / 2.0
 }
 
 impl Detector {
-    fn from_config(samp_rate: f32, target: PulseTarget) -> Detector {
-        let detector_samp_rate = samp_rate / SAMPLES_IN_WINDOW as f32;
-
-        let config = DetectorConfig {
-            samp_rate: detector_samp_rate,
-            duration: (target.duration * detector_samp_rate) as i64,
-            duration_variance: (target.duration_variance * detector_samp_rate) as i64,
-            threshold: target.threshold,
-            edge_length: target.edge_length,
-            peak_lookahead: target.peak_lookahead,
-        };
+    /// Create a detector for `target`. Pulses may be held for up to `release_delay` windows
+    /// after they are detected, which the frequency tracking history must also cover.
+    fn from_config(
+        id: usize,
+        samp_rate: f32,
+        center_freq: f32,
+        window_size: usize,
+        overlap: usize,
+        release_delay: usize,
+        target: PulseTarget,
+    ) -> Detector {
+        let period = target.period.map(|period| PeriodConfig {
+            period: (period * samp_rate) as u64,
+            mortality_period: target.mortality_period.map(|x| (x * samp_rate) as u64),
+            variance: (target.period_variance * samp_rate) as u64,
+        });
+
+        let config = detector_config(samp_rate, window_size, overlap, &target);
+
+        // The side bins are half a bin width from the target, and enough windows are kept to
+        // cover the delay between the start of a pulse and its detection.
+        let afc = target.afc.map(|afc| {
+            let spacing = samp_rate / window_size as f32 / 2.0;
+            let history = 2 * (config.duration + config.duration_variance) + config.edge_length
+                + config.peak_lookahead + 1 + release_delay as i64;
+            Afc::new(afc, spacing, history as usize)
+        });
+
+        let envelope_history = 2 * (config.duration + config.duration_variance) + config.edge_length
+            + config.peak_lookahead + 1;
+        let envelope = Envelope::new(config.samp_rate, envelope_history as usize);
+
+        let pulse_detector = new_pulse_detector(config, &target);
+        let durations = target.duration_range.map(|range| DurationHistogram::new(range, 1.0 / config.samp_rate));
 
         Detector {
-            target: target,
-            pulse_detector: PulseDetector::new(config),
+            id,
+            baseband_freq: target.freq - center_freq,
+            target,
+            pulse_detector,
+            history: PulseHistory::new(period),
+            status: TagStatus::Unknown,
+            afc,
+            envelope,
+            durations,
+            bin: 0,
+            afc_bins: [0, 0],
+        }
+    }
+
+    /// Discard the state that depends on the signal being continuous, after a gap in the stream.
+    /// A pulse that was in progress at the start of the gap is dropped, rather than being joined
+    /// to the signal after the gap. The pulse history, status and frequency estimate are kept.
+    fn restart(&mut self, samp_rate: f32, window_size: usize, overlap: usize) {
+        let config = detector_config(samp_rate, window_size, overlap, &self.target);
+        self.pulse_detector = new_pulse_detector(config, &self.target);
+        self.envelope.clear();
+        if let Some(ref mut afc) = self.afc {
+            afc.clear();
+        }
+    }
+}
+
+/// Create the pulse detector for a target. Edge detection works for any duration, but a single
+/// matched filter is only sensitive to pulses close to its length, so a bank of filters is used for
+/// a range of durations.
+fn new_pulse_detector(config: DetectorConfig, target: &PulseTarget) -> Box<dyn DetectPulses> {
+    match (target.algorithm, target.duration_range) {
+        (DetectionAlgorithm::Edge, _) => Box::new(PulseDetector::new(config)),
+        (DetectionAlgorithm::MatchedFilter, None) => Box::new(MatchedFilter::new(config)),
+        (DetectionAlgorithm::MatchedFilter, Some(range)) => {
+            let to_samples = |x: f32| (x * config.samp_rate) as i64;
+            let (min, max) = (to_samples(range.min), to_samples(range.max));
+            Box::new(MatchedFilterBank::new(config, min, max, to_samples(target.duration_variance)))
+        }
+    }
+}
+
+/// A builder for configuring the frequency filter used by `Detectors`.
+pub struct DetectorsBuilder {
+    window_size: usize,
+    window: WindowType,
+    channelizer_threshold: usize,
+    overlap: usize,
+    blanker: Option<BlankerConfig>,
+    cross_target_fraction: Option<f32>,
+    leakage: Option<LeakageConfig>,
+    iq_correction: Option<IqCorrectionConfig>,
+}
+
+impl Default for DetectorsBuilder {
+    fn default() -> DetectorsBuilder {
+        DetectorsBuilder {
+            window_size: DEFAULT_WINDOW_SIZE,
+            window: WindowType::BlackmanHarris,
+            channelizer_threshold: DEFAULT_CHANNELIZER_THRESHOLD,
+            overlap: 1,
+            blanker: None,
+            cross_target_fraction: None,
+            leakage: None,
+            iq_correction: None,
+        }
+    }
+}
+
+impl DetectorsBuilder {
+    pub fn new() -> DetectorsBuilder {
+        DetectorsBuilder::default()
+    }
+
+    /// Sets the number of samples in each analysis window. Larger windows give better frequency
+    /// selectivity at the cost of time resolution.
+    pub fn window_size(mut self, window_size: usize) -> DetectorsBuilder {
+        self.window_size = window_size.max(1);
+        self
+    }
+
+    /// Sets the windowing function applied to each analysis window
+    pub fn window(mut self, window: WindowType) -> DetectorsBuilder {
+        self.window = window;
+        self
+    }
+
+    /// Sets the maximum number of filter bins (one per target, plus two for each target with AFC)
+    /// that are computed individually. Above this, all bins are computed together using an FFT,
+    /// which is faster for large numbers of targets. The FFT is only used if the window size is a
+    /// power of two.
+    pub fn channelizer_threshold(mut self, threshold: usize) -> DetectorsBuilder {
+        self.channelizer_threshold = threshold;
+        self
+    }
+
+    /// Sets the number of overlapping windows covering each sample, e.g. 2 for windows that overlap
+    /// by 50%, or 4 for 75%. Overlapping windows improve the timing resolution of pulses, but the
+    /// cost of filtering grows in proportion to the overlap. The window size must be a multiple of
+    /// the overlap, otherwise the next smallest overlap that divides the window size is used.
+    pub fn overlap(mut self, overlap: usize) -> DetectorsBuilder {
+        self.overlap = overlap.max(1);
+        self
+    }
+
+    /// Enables the correction of the receiver's DC offset and I/Q imbalance, which is applied
+    /// before any other processing. This is needed for targets close to the center frequency on
+    /// receivers with a large DC offset (e.g. the HackRF), and removes the images of strong tags.
+    pub fn iq_correction(mut self, config: IqCorrectionConfig) -> DetectorsBuilder {
+        self.iq_correction = Some(config);
+        self
+    }
+
+    /// Enables a noise blanker that removes short broadband impulses from the signal before it is
+    /// filtered.
+    pub fn noise_blanker(mut self, config: BlankerConfig) -> DetectorsBuilder {
+        self.blanker = Some(config);
+        self
+    }
+
+    /// Rejects pulses that are detected at the same time by more than `fraction` of the targets
+    /// (and at least three targets), which are most likely caused by broadband interference rather
+    /// than tags. Pulses are held until the detectors of all other targets could have detected the
+    /// same interference, which delays their output by up to the longest pulse duration.
+    pub fn cross_target_rejection(mut self, fraction: f32) -> DetectorsBuilder {
+        self.cross_target_fraction = Some(fraction.max(0.0).min(1.0));
+        self
+    }
+
+    /// Checks whether pulses detected at the same time by targets with nearby frequencies are
+    /// leakage of the strongest pulse through the response of the filter window. Leakage is
+    /// flagged (or dropped) as configured, and pulses are delayed in the same way as for
+    /// `cross_target_rejection`. This overrides `SdrConfig::leakage_check`, which is used otherwise.
+    pub fn leakage_check(mut self, config: LeakageConfig) -> DetectorsBuilder {
+        self.leakage = Some(config);
+        self
+    }
+
+    /// Create a new set of detectors using the specified pulse targets
+    pub fn build(self, sdr_config: &SdrConfig, targets: &[PulseTarget]) -> Detectors {
+        let samp_rate = sdr_config.samp_rate as f32;
+        let offset = sdr_config.center_freq as f32;
+        let overlap = (1..=self.overlap.min(self.window_size))
+            .rev()
+            .find(|&x| self.window_size % x == 0)
+            .unwrap_or(1);
+        let leakage = self.leakage.or(sdr_config.leakage_check);
+
+        // Targets without an ID are identified by their index in `targets`. Targets outside the
+        // bandwidth of the SDR, or with the ID of an earlier target, are rejected.
+        let mut valid_targets: Vec<(usize, &PulseTarget)> = vec![];
+        let mut rejected_targets = vec![];
+        for (index, target) in targets.iter().enumerate() {
+            let id = target.id_or(index);
+            if valid_targets.iter().any(|x| x.0 == id) {
+                rejected_targets.push(rejection(id, target, RejectionReason::DuplicateId));
+            }
+            else if !in_band(samp_rate, offset, target) {
+                rejected_targets.push(rejection(id, target, RejectionReason::OutOfBand));
+            }
+            else {
+                valid_targets.push((id, target));
+            }
+        }
+
+        // When detections are compared across targets, pulses are held for long enough to compare
+        // all the detections.
+        let window_lut = self.window.generate_lut(self.window_size);
+        let mut checks = PulseChecks::new(samp_rate, window_lut.clone(), self.cross_target_fraction, leakage);
+        let release_delay = if checks.compares_targets() {
+            release_delay(samp_rate, self.window_size, overlap, valid_targets.iter().map(|x| x.1))
+        }
+        else {
+            0
+        };
+        checks.set_release_delay(release_delay as u64);
+
+        let mut detectors: Vec<_> = valid_targets.iter()
+            .map(|&(id, target)| {
+                Detector::from_config(id, samp_rate, offset, self.window_size, overlap, release_delay, target.clone())
+            })
+            .collect();
+
+        // The side bins used for AFC are placed after the bins of all the targets
+        let mut freqs = vec![];
+        for detector in &mut detectors {
+            detector.bin = freqs.len();
+            freqs.push(detector.baseband_freq);
+        }
+        for detector in &mut detectors {
+            if let Some(ref afc) = detector.afc {
+                let bins = afc.bin_offsets();
+                detector.afc_bins = [freqs.len(), freqs.len() + 1];
+                freqs.push(detector.baseband_freq + bins[0]);
+                freqs.push(detector.baseband_freq + bins[2]);
+            }
+        }
+
+        let use_channelizer = freqs.len() > self.channelizer_threshold && self.window_size.is_power_of_two();
+        let new_filter = || -> Box<dyn FreqFilter> {
+            if use_channelizer {
+                Box::new(Channelizer::new(samp_rate, freqs.iter().cloned(), window_lut.clone()))
+            }
+            else {
+                Box::new(FastGoertzel::new(samp_rate, freqs.iter().cloned(), window_lut.clone()))
+            }
+        };
+
+        let filter: Box<dyn FreqFilter> = if overlap > 1 {
+            Box::new(OverlappedFilter::new((0..overlap).map(|_| new_filter()).collect(), freqs.clone()))
+        }
+        else {
+            new_filter()
+        };
+
+        // The filter may not measure the exact frequency requested
+        for detector in &mut detectors {
+            let tuned = filter.freq(detector.bin) - detector.baseband_freq;
+            if let Some(ref mut afc) = detector.afc {
+                afc.set_tuned(tuned);
+            }
+        }
+
+        Detectors {
+            samp_rate: sdr_config.samp_rate,
+            center_freq: offset,
+            window_size: self.window_size,
+            hop: self.window_size / overlap,
+            sample_count: 0,
+            window_count: 0,
+            start_time: None,
+            filter,
+            free_bins: vec![],
+            detectors,
+            iq_correction: self.iq_correction.map(|config| IqCorrection::new(config, samp_rate)),
+            blanker: self.blanker.map(|config| NoiseBlanker::new(config, samp_rate)),
+            checks,
+            shape_pulses: 0,
+            status_changes: vec![],
+            duration_reports: vec![],
+            rejected_targets,
+            sample_format: sdr_config.sample_format,
         }
     }
 }
 
+/// Statistics about the interference removed by the detectors
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DetectorStats {
+    /// Statistics from the noise blanker. The number of samples is counted even if the blanker is
+    /// disabled.
+    pub blanker: BlankerStats,
+
+    /// The number of pulses rejected because they were detected for most targets at once
+    pub rejected_pulses: u64,
+
+    /// The number of pulses dropped as leakage of a stronger pulse from another target
+    pub leakage_pulses: u64,
+
+    /// The number of pulses dropped because their shape was outside the limits of their target
+    pub shape_pulses: u64,
+}
+
+/// An error from changing the targets of `Detectors`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TargetError {
+    /// There is already a target with the ID
+    DuplicateId(usize),
+
+    /// There is no target with the ID
+    UnknownId(usize),
+
+    /// The frequency of the target with the ID is outside the bandwidth of the SDR
+    OutOfBand(usize),
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TargetError::DuplicateId(id) => write!(f, "Target {} already exists", id),
+            TargetError::UnknownId(id) => write!(f, "Target {} does not exist", id),
+            TargetError::OutOfBand(id) => write!(f, "Target {} is outside the bandwidth of the SDR", id),
+        }
+    }
+}
+
+impl Error for TargetError {}
+
+fn rejection(id: usize, target: &PulseTarget, reason: RejectionReason) -> RejectedTarget {
+    RejectedTarget { target_id: id, name: target.name.clone(), reason }
+}
+
 /// A collection of detectors for detecting pulses with different frequencies and durations.
 pub struct Detectors {
+    samp_rate: u64,
+    center_freq: f32,
+    window_size: usize,
+
+    /// The number of samples between the start of consecutive (possibly overlapping) windows
+    hop: usize,
     sample_count: usize,
-    filter: FastGoertzel,
+    window_count: u64,
+    start_time: Option<Timestamp>,
+    filter: Box<dyn FreqFilter>,
+
+    /// Filter bins that were used by removed targets, which are reused for new targets
+    free_bins: Vec<usize>,
     detectors: Vec<Detector>,
+    iq_correction: Option<IqCorrection>,
+    blanker: Option<NoiseBlanker>,
+
+    /// The checks made on detected pulses against the pulses of other targets
+    checks: PulseChecks,
+    shape_pulses: u64,
+    status_changes: Vec<TargetStatus>,
+    duration_reports: Vec<DurationStats>,
+    rejected_targets: Vec<RejectedTarget>,
+    sample_format: SampleFormat,
 }
 
 impl Detectors {
-    /// Create a new set of detectors using the specified pulse targets
+    /// Create a new set of detectors using the specified pulse targets and the default filter
+    /// configuration
     pub fn new(sdr_config: &SdrConfig, targets: &[PulseTarget]) -> Detectors {
-        let samp_rate = sdr_config.samp_rate as f32;
-        let offset = sdr_config.center_freq as f32;
+        DetectorsBuilder::new().build(sdr_config, targets)
+    }
+
+    /// Sets the time of the first sample in the stream. If this is not set, the start time is
+    /// anchored when the first buffer of samples is received.
+    pub fn set_start_time(&mut self, start_time: Timestamp) {
+        self.start_time = Some(start_time);
+    }
+
+    /// Continue processing after a gap in the stream (e.g. while the SDR was tuned to another
+    /// frequency), where the next sample was captured at `timestamp`. See `resume_at`.
+    pub fn resume(&mut self, timestamp: Timestamp) {
+        let start_time = match self.start_time {
+            Some(start_time) => start_time,
+            None => {
+                self.start_time = Some(timestamp);
+                return;
+            }
+        };
 
-        let valid_targets: Vec<_> = targets.iter()
-            .filter(|target| (target.freq - offset).abs() < samp_rate / 2.0).collect();
+        let elapsed = util::nanos_between(start_time, timestamp);
+        self.resume_at(util::nanos_to_samples(elapsed, self.samp_rate));
+    }
 
-        let detectors = valid_targets.iter()
-            .map(|&&target| Detector::from_config(samp_rate, target)).collect();
+    /// Continue processing after a gap in the stream, where the next sample has the absolute index
+    /// `sample_index` (counted from the start time). The missing samples are skipped in whole
+    /// windows, so that pulse timestamps and periods remain consistent. Pulses that were in
+    /// progress at the start of the gap are dropped, but pulses that had already ended are kept.
+    pub fn resume_at(&mut self, sample_index: u64) {
+        self.window_count += sample_index.saturating_sub(self.sample_index()) / self.hop as u64;
 
-        Detectors {
-            sample_count: 0,
-            filter: FastGoertzel::new(samp_rate, valid_targets.iter().map(|x| x.freq - offset)),
-            detectors: detectors,
+        let overlap = self.window_size / self.hop;
+        for detector in &mut self.detectors {
+            detector.restart(self.samp_rate as f32, self.window_size, overlap);
         }
     }
 
-    /// Process the next set of samples (in u8, I/Q format), returning detected pulses.
-    pub fn next(&mut self, samples: &[u8]) -> Vec<Pulse> {
-        fn filter_samples(detectors: &mut Detectors, samples: &[u8]) {
-            detectors.sample_count += samples.len() / 2;
-            for sample in samples.chunks(2).map(|x| util::convert_iq(x[0], x[1])) {
-                detectors.filter.input(sample.1, sample.0);
+    /// The absolute index of the next sample to be processed
+    pub fn sample_index(&self) -> u64 {
+        self.window_count * self.hop as u64 + self.sample_count as u64
+    }
+
+    /// The current estimate of the receiver's DC offset and I/Q imbalance, if correction is enabled
+    pub fn iq_estimate(&self) -> Option<IqEstimate> {
+        self.iq_correction.as_ref().map(|x| x.estimate())
+    }
+
+    /// Statistics about the interference removed by the noise blanker, and the pulses dropped by
+    /// the cross-target, leakage and shape checks
+    pub fn stats(&self) -> DetectorStats {
+        let blanker = self.blanker.as_ref().map_or_else(
+            || BlankerStats { samples: self.sample_index(), ..BlankerStats::default() },
+            |blanker| *blanker.stats(),
+        );
+        let (rejected_pulses, leakage_pulses) = self.checks.dropped_pulses();
+        DetectorStats { blanker, rejected_pulses, leakage_pulses, shape_pulses: self.shape_pulses }
+    }
+
+    /// The IDs and configurations of the current targets
+    pub fn targets(&self) -> impl Iterator<Item = (usize, &PulseTarget)> {
+        self.detectors.iter().map(|x| (x.id, &x.target))
+    }
+
+    /// Start detecting pulses from a new target, which is identified by `id` in its pulses and
+    /// status changes. The state of the other targets is unaffected.
+    pub fn add_target(&mut self, id: usize, target: PulseTarget) -> Result<(), TargetError> {
+        if self.detector(id).is_some() {
+            return Err(TargetError::DuplicateId(id));
+        }
+        if !in_band(self.samp_rate as f32, self.center_freq, &target) {
+            return Err(TargetError::OutOfBand(id));
+        }
+
+        let mut detector = self.new_detector(id, target);
+        detector.bin = self.allocate_bin(detector.baseband_freq);
+        if detector.afc.is_some() {
+            detector.afc_bins = [self.allocate_bin(0.0), self.allocate_bin(0.0)];
+        }
+        self.tune(&mut detector);
+
+        self.detectors.push(detector);
+        self.update_release_delay();
+        Ok(())
+    }
+
+    /// Stop detecting pulses from a target, returning its configuration. Any of its pulses that
+    /// have not been output yet are discarded.
+    pub fn remove_target(&mut self, id: usize) -> Result<PulseTarget, TargetError> {
+        let index = self.detectors.iter().position(|x| x.id == id).ok_or(TargetError::UnknownId(id))?;
+        let detector = self.detectors.remove(index);
+
+        self.free_bins.push(detector.bin);
+        if detector.afc.is_some() {
+            self.free_bins.extend_from_slice(&detector.afc_bins);
+        }
+        self.checks.remove_target(id);
+
+        self.update_release_delay();
+        Ok(detector.target)
+    }
+
+    /// Change the configuration of a target. The pulse detector of the target is restarted, but
+    /// its pulse history is kept if the expected period is unchanged. The state of the other
+    /// targets is unaffected.
+    pub fn modify_target(&mut self, id: usize, target: PulseTarget) -> Result<(), TargetError> {
+        let index = self.detectors.iter().position(|x| x.id == id).ok_or(TargetError::UnknownId(id))?;
+        if !in_band(self.samp_rate as f32, self.center_freq, &target) {
+            return Err(TargetError::OutOfBand(id));
+        }
+        if self.detectors[index].target == target {
+            return Ok(());
+        }
+
+        let mut detector = self.new_detector(id, target.clone());
+        let old = self.detectors.remove(index);
+
+        let same_period = old.target.period == target.period
+            && old.target.mortality_period == target.mortality_period
+            && old.target.period_variance == target.period_variance;
+        if same_period {
+            detector.history = old.history;
+            detector.status = old.status;
+        }
+
+        detector.bin = old.bin;
+        match (old.afc.is_some(), detector.afc.is_some()) {
+            (true, true) => detector.afc_bins = old.afc_bins,
+            (true, false) => self.free_bins.extend_from_slice(&old.afc_bins),
+            (false, true) => detector.afc_bins = [self.allocate_bin(0.0), self.allocate_bin(0.0)],
+            (false, false) => {}
+        }
+        self.tune(&mut detector);
+
+        self.detectors.insert(index, detector);
+        self.checks.remove_target(id);
+        self.update_release_delay();
+        Ok(())
+    }
+
+    /// Replace the current targets with `targets`, adding, removing and modifying targets as
+    /// needed. Targets are matched by ID in the same way as when the detectors are built, and
+    /// targets that are unchanged keep their state. Targets that are rejected are reported by
+    /// `rejected_targets`.
+    pub fn update_targets(&mut self, targets: &[PulseTarget]) {
+        let ids: Vec<_> = targets.iter().enumerate().map(|(index, target)| target.id_or(index)).collect();
+        let removed: Vec<_> = self.detectors.iter()
+            .map(|x| x.id)
+            .filter(|id| !ids.contains(id))
+            .collect();
+        for id in removed {
+            let _ = self.remove_target(id);
+        }
+
+        for (index, (&id, target)) in ids.iter().zip(targets).enumerate() {
+            if ids[..index].contains(&id) {
+                self.rejected_targets.push(rejection(id, target, RejectionReason::DuplicateId));
+                continue;
+            }
+            let result = match self.modify_target(id, target.clone()) {
+                Err(TargetError::UnknownId(_)) => self.add_target(id, target.clone()),
+                result => result,
+            };
+            if let Err(TargetError::OutOfBand(_)) = result {
+                let _ = self.remove_target(id);
+                self.rejected_targets.push(rejection(id, target, RejectionReason::OutOfBand));
             }
         }
+    }
 
-        self.next_buffer(samples, filter_samples)
+    fn detector(&self, id: usize) -> Option<&Detector> {
+        self.detectors.iter().find(|x| x.id == id)
     }
 
-    /// Process the next set of samples (in float32, I/Q format), returning detected pulses.
-    pub fn next_f32(&mut self, samples: &[f32]) -> Vec<Pulse> {
-        fn filter_samples_f32(detectors: &mut Detectors, samples: &[f32]) {
-            detectors.sample_count += samples.len() / 2;
-            for sample in samples.chunks(2) {
-                detectors.filter.input(sample[1], sample[0]);
+    fn new_detector(&self, id: usize, target: PulseTarget) -> Detector {
+        let overlap = self.window_size / self.hop;
+        let release_delay = self.checks.release_delay() as usize;
+        let (samp_rate, center_freq) = (self.samp_rate as f32, self.center_freq);
+        Detector::from_config(id, samp_rate, center_freq, self.window_size, overlap, release_delay, target)
+    }
+
+    /// Get a filter bin for a new target, reusing the bin of a removed target if possible
+    fn allocate_bin(&mut self, freq: f32) -> usize {
+        match self.free_bins.pop() {
+            Some(bin) => {
+                self.filter.set_freq(bin, freq);
+                bin
             }
+            None => self.filter.add_freq(freq),
+        }
+    }
+
+    /// Tune the filter bins of `detector` to its frequency
+    fn tune(&mut self, detector: &mut Detector) {
+        self.filter.set_freq(detector.bin, detector.baseband_freq);
+        let tuned = self.filter.freq(detector.bin) - detector.baseband_freq;
+
+        if let Some(ref mut afc) = detector.afc {
+            let bins = afc.bin_offsets();
+            self.filter.set_freq(detector.afc_bins[0], detector.baseband_freq + bins[0]);
+            self.filter.set_freq(detector.afc_bins[1], detector.baseband_freq + bins[2]);
+            afc.set_tuned(tuned);
+        }
+    }
+
+    /// Recompute the time that pulses are held for after the targets have changed
+    fn update_release_delay(&mut self) {
+        if self.checks.compares_targets() {
+            let overlap = self.window_size / self.hop;
+            let targets = self.detectors.iter().map(|x| &x.target);
+            let delay = release_delay(self.samp_rate as f32, self.window_size, overlap, targets);
+            self.checks.set_release_delay(delay as u64);
         }
+    }
+
+    /// Returns any changes to the status of the targets (see `TagStatus`) since the last call.
+    pub fn status_changes(&mut self) -> Vec<TargetStatus> {
+        mem::replace(&mut self.status_changes, vec![])
+    }
+
+    /// Returns the reports of the measured pulse durations of targets with a duration range since
+    /// the last call. A report is made every few pulses, and when a target is tightened to its
+    /// measured duration.
+    pub fn duration_reports(&mut self) -> Vec<DurationStats> {
+        mem::replace(&mut self.duration_reports, vec![])
+    }
+
+    /// Returns the targets that have been rejected (e.g. because they are outside the bandwidth of
+    /// the SDR) since the last call. Pulses are not detected for rejected targets.
+    pub fn rejected_targets(&mut self) -> Vec<RejectedTarget> {
+        mem::replace(&mut self.rejected_targets, vec![])
+    }
+
+    /// Process the next set of samples (in signed 8-bit, I/Q format), returning detected pulses.
+    pub fn next(&mut self, samples: &[u8]) -> Vec<Pulse> {
+        self.next_buffer(samples, 2, |x| sample_format::convert_cs8(x[0], x[1]))
+    }
 
-        self.next_buffer(samples, filter_samples_f32)
+    /// Process the next set of samples (in unsigned 8-bit, I/Q format), returning detected pulses.
+    pub fn next_cu8(&mut self, samples: &[u8]) -> Vec<Pulse> {
+        self.next_buffer(samples, 2, |x| sample_format::convert_cu8(x[0], x[1]))
     }
 
-    fn next_buffer<T, F>(&mut self, samples: &[T], filter: F) -> Vec<Pulse>
-        where F: Fn(&mut Detectors, &[T])
+    /// Process the next set of samples (in signed 16-bit, I/Q format), returning detected pulses.
+    pub fn next_cs16(&mut self, samples: &[i16]) -> Vec<Pulse> {
+        self.next_buffer(samples, 2, |x| sample_format::convert_cs16(x[0], x[1]))
+    }
+
+    /// Process the next set of samples (in float32, I/Q format), returning detected pulses.
+    pub fn next_f32(&mut self, samples: &[f32]) -> Vec<Pulse> {
+        self.next_buffer(samples, 2, |x| (x[0], x[1]))
+    }
+
+    /// Process the next set of raw samples in the format given by `SdrConfig::sample_format`,
+    /// returning detected pulses. `samples` must contain a whole number of samples.
+    pub fn next_bytes(&mut self, samples: &[u8]) -> Vec<Pulse> {
+        let convert = sample_format::byte_converter(self.sample_format);
+        self.next_buffer(samples, self.sample_format.sample_size(), convert)
+    }
+
+    /// Process a buffer of samples, where each sample consists of `sample_len` values that are
+    /// converted to an (I, Q) pair by `convert`.
+    fn next_buffer<T, F>(&mut self, samples: &[T], sample_len: usize, convert: F) -> Vec<Pulse>
+        where F: Fn(&[T]) -> (f32, f32)
     {
+        let num_samples = (samples.len() / sample_len) as u64;
+        if self.start_time.is_none() {
+            // The buffer has just been received, so the first sample was captured one buffer
+            // length ago.
+            let buffer_nanos = util::samples_to_nanos(num_samples, self.samp_rate);
+            self.start_time = Some(Timestamp::now().sub_nanos(buffer_nanos));
+        }
+
+        let filter = |detectors: &mut Detectors, samples: &[T]| {
+            detectors.sample_count += samples.len() / sample_len;
+            for (i, q) in samples.chunks(sample_len).map(&convert) {
+                let (i, q) = match detectors.iq_correction {
+                    Some(ref mut correction) => correction.input(i, q),
+                    None => (i, q),
+                };
+                let (i, q) = match detectors.blanker {
+                    Some(ref mut blanker) => blanker.input(i, q),
+                    None => (i, q),
+                };
+                detectors.filter.input(q, i);
+            }
+        };
+
         let mut pulses = vec![];
 
-        for chunk in samples.chunks(SAMPLES_IN_WINDOW * 2) {
-            let size = cmp::min(chunk.len(), (SAMPLES_IN_WINDOW - self.sample_count) * 2);
+        for chunk in samples.chunks(self.hop * sample_len) {
+            let size = cmp::min(chunk.len(), (self.hop - self.sample_count) * sample_len);
 
             filter(self, &chunk[0..size]);
 
-            if self.sample_count >= SAMPLES_IN_WINDOW {
+            if self.sample_count >= self.hop {
                 self.check_detectors(&mut pulses);
                 self.sample_count = 0;
+                self.window_count += 1;
             }
 
             // Finish process any excess data
@@ -115,23 +804,629 @@ impl Detectors {
         pulses
     }
 
-     /// Process the output from the filter and check the detectors for pulses
+    /// Output the pulses that have been held for long enough to be checked against the pulses of
+    /// other targets
+    fn release_pulses(&mut self, pulse_buffer: &mut Vec<Pulse>) {
+        let start_time = self.start_time.unwrap_or(Timestamp { seconds: 0, nanos: 0 });
+        let samp_rate = self.samp_rate as f32;
+        let released = {
+            let (detectors, filter) = (&self.detectors, &self.filter);
+            let freq = |id| detectors.iter().find(|x| x.id == id).map(|x| filter.freq(x.bin));
+            self.checks.release(self.window_count, detectors.len(), freq)
+        };
+        let mut tightened = vec![];
+
+        for (candidate, flag) in released {
+            let id = candidate.target;
+            let pulse = candidate.pulse;
+            let shape = candidate.shape;
+            let start_sample = candidate.start_sample;
+            let window = candidate.window;
+
+            let detector = match self.detectors.iter_mut().find(|x| x.id == id) {
+                Some(detector) => detector,
+                None => continue,
+            };
+
+            let interval = detector.history.record(start_sample);
+            if detector.target.filter_by_period && !interval.map_or(false, |x| x.consistent) {
+                continue;
+            }
+
+            let freq_offset = match detector.afc {
+                Some(ref mut afc) => {
+                    let start_offset = pulse.start_offset as u64 + self.window_count - window;
+                    afc.pulse(start_offset as usize, pulse.duration as usize)
+                }
+                None => 0.0,
+            };
+
+            let timestamp = start_time.add_nanos(util::samples_to_nanos(start_sample, self.samp_rate));
+            let duration = pulse.duration as f32 / detector.pulse_detector.config().samp_rate;
+            if let Some(ref mut durations) = detector.durations {
+                match durations.update(id, &detector.target, duration, timestamp) {
+                    DurationUpdate::None => {}
+                    DurationUpdate::Report(stats) => self.duration_reports.push(stats),
+                    // The report is made once the target has been changed
+                    DurationUpdate::Tighten(target, stats) => tightened.push((id, target, stats)),
+                }
+            }
+
+            pulse_buffer.push(Pulse {
+                target_id: id,
+                freq: detector.target.freq + freq_offset,
+                duration,
+                signal_strength: pulse.max_signal_strength,
+                noise_floor: pulse.noise_floor,
+                snr: pulse.noise_floor
+                    .filter(|&floor| floor > 0.0)
+                    .map(|floor| 20.0 * (pulse.mean_signal_strength / floor).log10()),
+                period: interval.map(|x| x.period as f32 / samp_rate),
+                gain: detector.target.gain,
+                timestamp,
+                flag,
+                slot: None,
+                shape,
+            });
+        }
+
+        for (id, target, mut stats) in tightened {
+            match self.modify_target(id, target.clone()) {
+                Ok(()) => {
+                    stats.tightened = true;
+                    stats.target = Some(target);
+                }
+                Err(e) => stats.error = Some(e.to_string()),
+            }
+            self.duration_reports.push(stats);
+        }
+    }
+
+    /// Process the output from the filter and check the detectors for pulses
     fn check_detectors(&mut self, pulse_buffer: &mut Vec<Pulse>) {
+        let start_time = self.start_time.unwrap_or(Timestamp { seconds: 0, nanos: 0 });
+        let samp_rate = self.samp_rate as f32;
         let output = self.filter.output();
 
-        for (i, (detector, &sample)) in self.detectors.iter_mut().zip(output.iter()).enumerate() {
+        for detector in &mut self.detectors {
+            let sample = output[detector.bin];
+            if let Some(ref mut afc) = detector.afc {
+                afc.input([output[detector.afc_bins[0]], sample, output[detector.afc_bins[1]]]);
+            }
+
+            detector.envelope.input(sample);
+
             if let Some(pulse) = detector.pulse_detector.input(sample) {
-                let pulse_with_freq = Pulse {
-                    target_id: i,
-                    freq: detector.target.freq,
-                    duration: pulse.duration as f32 / detector.pulse_detector.config.samp_rate,
-                    signal_strength: pulse.max_signal_strength,
-                    gain: detector.target.gain,
-                    timestamp: Timestamp::now(),
+                let shape = detector.envelope.shape(pulse.start_offset as usize, pulse.duration as usize);
+                let rejected = match (shape, detector.target.shape_limits) {
+                    (Some(shape), Some(limits)) => !limits.accepts(&shape),
+                    _ => false,
                 };
+                if rejected {
+                    self.shape_pulses += 1;
+                    continue;
+                }
+
+                // Find the first sample of the window where the rising edge was detected. With
+                // overlapping windows the edge is found in the first window that the pulse mostly
+                // covers, so the pulse starts (on average) halfway through the overlap of that window.
+                // The noise blanker delays the signal, so its delay is removed as well.
+                let start_window = self.window_count.saturating_sub(pulse.start_offset as u64);
+                let start_sample = ((start_window + 1) * self.hop as u64)
+                    .saturating_sub(((self.window_size + self.hop) / 2) as u64)
+                    .saturating_sub(self.blanker.as_ref().map_or(0, |x| x.delay() as u64));
+
+                let window = self.window_count;
+                self.checks.push(Candidate { target: detector.id, pulse, shape, start_sample, window });
+            }
+        }
+
+        self.release_pulses(pulse_buffer);
+
+        for detector in &mut self.detectors {
+            let afc = match detector.afc {
+                Some(ref mut afc) => afc,
+                None => continue,
+            };
+
+            if let Some(bins) = afc.take_retune() {
+                self.filter.set_freq(detector.afc_bins[0], detector.baseband_freq + bins[0]);
+                self.filter.set_freq(detector.bin, detector.baseband_freq + bins[1]);
+                self.filter.set_freq(detector.afc_bins[1], detector.baseband_freq + bins[2]);
+                afc.set_tuned(self.filter.freq(detector.bin) - detector.baseband_freq);
+            }
+        }
+
+        let current_sample = self.window_count * self.hop as u64;
+        for detector in &mut self.detectors {
+            let status = detector.history.status(current_sample);
+            if status != detector.status {
+                detector.status = status;
+                self.status_changes.push(TargetStatus {
+                    target_id: detector.id,
+                    status,
+                    period: detector.history.median_interval().map(|x| x as f32 / samp_rate),
+                    timestamp: start_time.add_nanos(util::samples_to_nanos(current_sample, self.samp_rate)),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::{AfcConfig, DurationRange, ShapeLimits};
+    use signal_gen::{add_pulse, tag_target, Generator, GeneratorConfig, TagConfig};
+    use std::f32::consts::PI;
+    use std::slice;
+
+    fn pulse_signal(samp_rate: f32, freq: f32, len: usize, start: usize, end: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; len * 2];
+        add_pulse(&mut samples, samp_rate, freq, start, end);
+        samples
+    }
+
+    #[test]
+    fn pulse_timestamp_from_sample_index() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let mut detectors = Detectors::new(&sdr_config, &[tag_target(150_100_000.0)]);
+        detectors.set_start_time(Timestamp { seconds: 100, nanos: 0 });
+
+        // 20 ms pulse starting 50 ms into the stream
+        let signal = pulse_signal(1_024_000.0, 100_000.0, 102_400, 51_200, 71_680);
+
+        let pulses: Vec<_> = signal.chunks(4096 * 2).flat_map(|x| detectors.next_f32(x)).collect();
+        assert_eq!(pulses.len(), 1);
+        assert_eq!(detectors.sample_index(), 102_400);
+
+        let timestamp = pulses[0].timestamp;
+        assert_eq!(timestamp.seconds, 100);
+        assert!((timestamp.nanos as i64 - 50_000_000).abs() <= 1_000_000, "{:?}", timestamp);
+    }
+
+    #[test]
+    fn configurable_window() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let signal = pulse_signal(1_024_000.0, 100_000.0, 102_400, 51_200, 71_680);
+
+        for &(window_size, window) in &[(256, WindowType::Hann), (2048, WindowType::Kaiser(6.0))] {
+            let mut detectors = DetectorsBuilder::new()
+                .window_size(window_size)
+                .window(window)
+                .build(&sdr_config, &[tag_target(150_100_000.0)]);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses = detectors.next_f32(&signal);
+            assert_eq!(pulses.len(), 1, "window_size = {}", window_size);
+            assert!((pulses[0].duration - 0.02).abs() <= 2.0 * window_size as f32 / 1_024_000.0);
+        }
+    }
+
+    #[test]
+    fn period_filter() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let target = PulseTarget {
+            period: Some(0.3),
+            period_variance: 0.005,
+            filter_by_period: true,
+            ..tag_target(150_100_000.0)
+        };
+        let mut detectors = Detectors::new(&sdr_config, &[target]);
+
+        // Pulses every 300 ms, with a spurious pulse 100 ms after the second pulse
+        let mut signal = vec![0.0; 1_024_000 * 2];
+        for &start in &[51_200, 358_400, 460_800, 665_600, 972_800] {
+            add_pulse(&mut signal, 1_024_000.0, 100_000.0, start, start + 20_480);
+        }
+
+        // The first pulse cannot be validated and the spurious pulse is inconsistent
+        let pulses = detectors.next_f32(&signal);
+        assert_eq!(pulses.len(), 3);
+        for pulse in pulses {
+            assert!((pulse.period.unwrap() - 0.3).abs() < 0.005, "{:?}", pulse.period);
+        }
+    }
+
+    #[test]
+    fn mortality_status_change() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let target = PulseTarget {
+            period: Some(0.2),
+            mortality_period: Some(0.1),
+            period_variance: 0.005,
+            ..tag_target(150_100_000.0)
+        };
+        let mut detectors = Detectors::new(&sdr_config, &[target]);
+
+        // Pulses every 200 ms for 1 second, then every 100 ms for 1 second
+        let mut signal = vec![0.0; 2 * 1_024_000 * 2];
+        let normal = (0..5).map(|i| 51_200 + i * 204_800);
+        let mortality = (0..10).map(|i| 1_075_200 + i * 102_400);
+        for start in normal.chain(mortality) {
+            add_pulse(&mut signal, 1_024_000.0, 100_000.0, start, start + 20_480);
+        }
+
+        detectors.next_f32(&signal);
+        let statuses: Vec<_> = detectors.status_changes().iter().map(|x| x.status).collect();
+        assert_eq!(statuses, vec![TagStatus::Normal, TagStatus::Mortality]);
+        assert!(detectors.status_changes().is_empty());
+    }
+
+    #[test]
+    fn afc_tracks_drifted_target() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let target = PulseTarget {
+            afc: Some(AfcConfig { max_offset: 1000.0, rate: 0.5 }),
+            ..tag_target(150_100_000.0)
+        };
+        let mut detectors = Detectors::new(&sdr_config, &[target]);
+
+        // The tag has drifted 300 Hz above its configured frequency
+        let mut signal = vec![0.0; 1_024_000 * 2];
+        for i in 0..9 {
+            let start = 51_200 + i * 102_400;
+            add_pulse(&mut signal, 1_024_000.0, 100_300.0, start, start + 20_480);
+        }
+
+        let pulses = detectors.next_f32(&signal);
+        assert_eq!(pulses.len(), 9);
+        for pulse in &pulses[4..] {
+            assert!((pulse.freq - 150_100_300.0).abs() < 30.0, "{}", pulse.freq);
+        }
+    }
+
+    #[test]
+    fn channelizer_backend() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let targets = [
+            tag_target(150_100_000.0),
+            PulseTarget { afc: Some(AfcConfig { max_offset: 1000.0, rate: 0.5 }), ..tag_target(149_800_000.0) },
+        ];
+
+        let mut signal = vec![0.0; 1_024_000 * 2];
+        for i in 0..9 {
+            let start = 51_200 + i * 102_400;
+            add_pulse(&mut signal, 1_024_000.0, 100_000.0, start, start + 20_480);
+            add_pulse(&mut signal, 1_024_000.0, -199_700.0, start + 40_960, start + 61_440);
+        }
+
+        let mut goertzel = DetectorsBuilder::new().build(&sdr_config, &targets);
+        let mut channelizer = DetectorsBuilder::new().channelizer_threshold(0).build(&sdr_config, &targets);
+
+        let expected = goertzel.next_f32(&signal);
+        let actual = channelizer.next_f32(&signal);
+        assert_eq!(expected.len(), 18);
+        assert_eq!(actual.len(), 18);
+
+        for (expected, actual) in expected.iter().zip(&actual) {
+            assert_eq!(expected.target_id, actual.target_id);
+            let error = (expected.signal_strength - actual.signal_strength) / expected.signal_strength;
+            assert!(error.abs() < 0.05, "{} != {}", expected.signal_strength, actual.signal_strength);
+        }
+
+        // The channelizer cannot tune to the exact frequency, but still estimates it
+        let last = actual.iter().rev().find(|x| x.target_id == 1).unwrap();
+        assert!((last.freq - 149_800_300.0).abs() < 50.0, "{}", last.freq);
+    }
+
+    #[test]
+    fn sample_formats() {
+        let signal: Vec<f32> = pulse_signal(1_024_000.0, 100_000.0, 102_400, 51_200, 71_680)
+            .iter().map(|x| x * 0.5).collect();
+
+        let cu8: Vec<u8> = signal.iter().map(|x| (x * 128.0 + 128.0) as u8).collect();
+        let cs8: Vec<u8> = signal.iter().map(|x| (x * 128.0) as i8 as u8).collect();
+        let cs16: Vec<u8> = signal.iter().flat_map(|x| ((x * 32768.0) as i16).to_le_bytes().to_vec()).collect();
+        let cf32: Vec<u8> = signal.iter().flat_map(|x| x.to_bits().to_le_bytes().to_vec()).collect();
 
-                pulse_buffer.push(pulse_with_freq);
+        let inputs = [
+            (SampleFormat::Cu8, cu8),
+            (SampleFormat::Cs8, cs8),
+            (SampleFormat::Cs16, cs16),
+            (SampleFormat::Cf32, cf32),
+        ];
+        for (sample_format, bytes) in inputs.iter().cloned() {
+            let sdr_config = SdrConfig {
+                samp_rate: 1_024_000,
+                center_freq: 150_000_000,
+                sample_format,
+                ..SdrConfig::default()
+            };
+            let mut detectors = Detectors::new(&sdr_config, &[tag_target(150_100_000.0)]);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = bytes.chunks(4096).flat_map(|x| detectors.next_bytes(x)).collect();
+            assert_eq!(pulses.len(), 1, "{}", sample_format);
+            assert_eq!(detectors.sample_index(), 102_400);
+            assert!((pulses[0].timestamp.nanos as i64 - 50_000_000).abs() <= 1_000_000);
+        }
+    }
+
+    #[test]
+    fn synthetic_signal() {
+        let samp_rate = 1_024_000;
+        let tag = |freq, period, first_pulse, drift| TagConfig {
+            drift,
+            phase_noise: 0.1,
+            ..TagConfig::new(freq, period, 0.5, first_pulse)
+        };
+        let tags = vec![tag(100_000.0, 0.9, 0.25, 0.0), tag(-200_000.0, 1.1, 0.6, 50.0)];
+        let generator_config = GeneratorConfig::new(samp_rate, tags, 0.1, 7);
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+
+        for &algorithm in &[DetectionAlgorithm::Edge, DetectionAlgorithm::MatchedFilter] {
+            let mut generator = Generator::new(generator_config.clone());
+
+            let targets = [
+                PulseTarget { algorithm, ..tag_target(150_100_000.0) },
+                PulseTarget { algorithm, ..tag_target(149_800_000.0) },
+            ];
+            let mut detectors = Detectors::new(&sdr_config, &targets);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let mut truth = vec![];
+            let mut pulses = vec![];
+            for _ in 0..50 {
+                let (samples, true_pulses) = generator.generate(102_400);
+                truth.extend(true_pulses);
+                pulses.extend(detectors.next_f32(&samples));
             }
+
+            // Every pulse is detected at the right time, with no false alarms
+            let evaluation = evaluation::evaluate(&truth, &pulses, samp_rate, 5.0, 0.002);
+            assert_eq!(evaluation.detected, truth.len(), "{:?}: {:?}", algorithm, evaluation);
+            assert_eq!(evaluation.false_alarms, 0, "{:?}: {:?}", algorithm, evaluation);
+        }
+    }
+
+    #[test]
+    fn overlapping_windows() {
+        let samp_rate = 1_024_000;
+        let tags = vec![TagConfig::new(100_300.0, 0.3137, 0.5, 0.1)];
+        let generator_config = GeneratorConfig::new(samp_rate, tags, 0.05, 3);
+        let (samples, truth) = Generator::new(generator_config).generate(5 * samp_rate as usize);
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let target = PulseTarget {
+            afc: Some(AfcConfig { max_offset: 1000.0, rate: 0.5 }),
+            ..tag_target(150_100_000.0)
+        };
+
+        let mut timing_errors = vec![];
+        for &overlap in &[1, 4] {
+            let builder = DetectorsBuilder::new().overlap(overlap);
+            let mut detectors = builder.build(&sdr_config, slice::from_ref(&target));
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = samples.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+            assert_eq!(detectors.sample_index(), 5 * samp_rate);
+
+            let evaluation = evaluation::evaluate(&truth, &pulses, samp_rate, 5.0, 0.002);
+            assert_eq!(evaluation.detected, truth.len(), "overlap = {}", overlap);
+            assert_eq!(evaluation.false_alarms, 0, "overlap = {}", overlap);
+            timing_errors.push(evaluation.timing_error);
+
+            // The frequency is still tracked when the bins are retuned between overlapping windows
+            let last = pulses.last().unwrap();
+            assert!((last.freq - 150_100_300.0).abs() < 50.0, "{}", last.freq);
         }
+
+        assert!(timing_errors[1] < 0.5 * timing_errors[0], "{:?}", timing_errors);
+    }
+
+    #[test]
+    fn duration_range() {
+        let samp_rate = 1_024_000.0;
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+
+        // Five 30 ms pulses, 100 ms apart
+        let mut signal = vec![0.0; 2 * 563_200];
+        for i in 0..5 {
+            let start = 51_200 + i * 102_400;
+            add_pulse(&mut signal, samp_rate, 100_000.0, start, start + 30_720);
+        }
+
+        for &algorithm in &[DetectionAlgorithm::Edge, DetectionAlgorithm::MatchedFilter] {
+            let range = DurationRange { min: 0.01, max: 0.04, tighten_after: Some(3) };
+            let target = PulseTarget { duration_range: Some(range), algorithm, ..tag_target(150_100_000.0) };
+            let mut detectors = Detectors::new(&sdr_config, &[target]);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = signal.chunks(4096 * 2).flat_map(|x| detectors.next_f32(x)).collect();
+            assert_eq!(pulses.len(), 5, "{:?}: {:?}", algorithm, pulses);
+            assert!(pulses.iter().all(|x| (x.duration - 0.03).abs() <= 0.002), "{:?}: {:?}", algorithm, pulses);
+
+            // The target is narrowed to the measured duration after the third pulse
+            let reports = detectors.duration_reports();
+            assert_eq!(reports.len(), 1, "{:?}", algorithm);
+            assert!(reports[0].tightened && reports[0].count == 3);
+            assert_eq!(reports[0].histogram.iter().sum::<usize>(), 3);
+
+            let (_, target) = detectors.targets().next().unwrap();
+            assert_eq!(reports[0].target.as_ref(), Some(target));
+            assert!(target.duration_range.is_none());
+            assert!((target.duration - 0.03).abs() <= 0.002, "{:?}", target);
+            assert!(target.duration_variance < 0.005, "{:?}", target);
+        }
+    }
+
+    #[test]
+    fn shape_limits() {
+        let samp_rate = 1_024_000.0;
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+
+        // A keyed pulse at 50 ms, followed by a 26 ms burst at 150 ms that fades in and out over 6 ms
+        let mut signal = pulse_signal(samp_rate, 100_000.0, 204_800, 51_200, 71_680);
+        let (start, end, ramp) = (153_600, 180_224, 6_144);
+        for n in start..end {
+            let amplitude = ((n - start).min(end - n) as f32 / ramp as f32).min(1.0);
+            let phase = 2.0 * PI * 100_000.0 * n as f32 / samp_rate;
+            signal[2 * n] += amplitude * phase.cos();
+            signal[2 * n + 1] += amplitude * phase.sin();
+        }
+
+        let limits = ShapeLimits { max_rise_time: Some(0.003), max_fall_time: Some(0.003), ..ShapeLimits::default() };
+        for &(shape_limits, expected) in &[(None, 2), (Some(limits), 1)] {
+            let target = PulseTarget { shape_limits, ..tag_target(150_100_000.0) };
+            let mut detectors = Detectors::new(&sdr_config, &[target]);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = signal.chunks(4096 * 2).flat_map(|x| detectors.next_f32(x)).collect();
+            assert_eq!(pulses.len(), expected, "{:?}", pulses);
+            assert_eq!(detectors.stats().shape_pulses as usize, 2 - expected);
+
+            let shape = pulses[0].shape.unwrap();
+            assert!(shape.rise_time <= 0.002 && shape.fall_time <= 0.002, "{:?}", shape);
+            assert!(shape.flatness > 0.9 && shape.on_off_ratio > 20.0, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn resume_after_gap() {
+        let samp_rate = 256_000;
+        let tags = vec![TagConfig::new(100_000.0, 1.0, 0.5, 0.25)];
+        let mut generator = Generator::new(GeneratorConfig::new(samp_rate, tags, 0.05, 5));
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let target = PulseTarget { period: Some(1.0), period_variance: 0.01, ..tag_target(150_100_000.0) };
+        let mut detectors = Detectors::new(&sdr_config, &[target]);
+        detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+        // The samples between 1.5 and 5.5 seconds are never received
+        let (first, _) = generator.generate(3 * samp_rate as usize / 2);
+        let mut pulses: Vec<_> = first.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+        generator.generate(4 * samp_rate as usize);
+        detectors.resume(Timestamp { seconds: 5, nanos: 500_000_000 });
+        let (second, _) = generator.generate(2 * samp_rate as usize);
+        pulses.extend(second.chunks(10_000).flat_map(|x| detectors.next_f32(x)));
+
+        let times: Vec<_> = pulses.iter()
+            .map(|x| x.timestamp.seconds as f64 + x.timestamp.nanos as f64 * 1e-9)
+            .collect();
+        let expected = [0.25, 1.25, 6.25, 7.25];
+        assert_eq!(times.len(), expected.len(), "{:?}", times);
+        assert!(times.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 0.005), "{:?}", times);
+        assert!(pulses[3].period.map_or(false, |x| (x - 1.0).abs() < 0.01), "{:?}", pulses[3].period);
+    }
+
+    #[test]
+    fn resume_drops_straddling_pulse() {
+        let samp_rate = 256_000;
+        let tags = vec![TagConfig::new(100_000.0, 1.0, 0.5, 0.25)];
+        let mut generator = Generator::new(GeneratorConfig::new(samp_rate, tags, 0.05, 6));
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let mut detectors = Detectors::new(&sdr_config, &[tag_target(150_100_000.0)]);
+        detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+        // The gap starts in the middle of the pulse at 1.25 seconds and ends in the middle of the
+        // pulse at 5.25 seconds, so neither pulse is received in full
+        let (first, _) = generator.generate(126 * samp_rate as usize / 100);
+        let mut pulses: Vec<_> = first.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+        generator.generate(4 * samp_rate as usize);
+        detectors.resume_at(526 * samp_rate / 100);
+        let (second, _) = generator.generate(5 * samp_rate as usize / 2);
+        pulses.extend(second.chunks(10_000).flat_map(|x| detectors.next_f32(x)));
+
+        let times: Vec<_> = pulses.iter()
+            .map(|x| x.timestamp.seconds as f64 + x.timestamp.nanos as f64 * 1e-9)
+            .collect();
+        let expected = [0.25, 6.25, 7.25];
+        assert_eq!(times.len(), expected.len(), "{:?}", times);
+        assert!(times.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 0.005), "{:?}", times);
+    }
+
+    #[test]
+    fn explicit_target_ids() {
+        let sdr_config = SdrConfig { samp_rate: 1_024_000, center_freq: 150_000_000, ..SdrConfig::default() };
+        let named = |id, name: &str, freq| PulseTarget {
+            id: Some(id),
+            name: Some(name.into()),
+            ..tag_target(freq)
+        };
+        let targets = [
+            named(7, "far", 151_000_000.0),
+            tag_target(150_100_000.0),
+            named(1, "duplicate", 150_200_000.0),
+            named(3, "near", 150_300_000.0),
+        ];
+        let mut detectors = Detectors::new(&sdr_config, &targets);
+
+        // IDs are unaffected by the targets that are rejected
+        let ids: Vec<_> = detectors.targets().map(|x| x.0).collect();
+        assert_eq!(ids, [1, 3]);
+
+        let rejected = detectors.rejected_targets();
+        assert_eq!(rejected, [
+            RejectedTarget { target_id: 7, name: Some("far".into()), reason: RejectionReason::OutOfBand },
+            RejectedTarget { target_id: 1, name: Some("duplicate".into()), reason: RejectionReason::DuplicateId },
+        ]);
+        assert!(detectors.rejected_targets().is_empty());
+
+        detectors.update_targets(&[named(3, "near", 150_300_000.0), named(4, "far", 149_000_000.0)]);
+        let ids: Vec<_> = detectors.targets().map(|x| x.0).collect();
+        assert_eq!(ids, [3]);
+        let rejected: Vec<_> = detectors.rejected_targets().iter().map(|x| (x.target_id, x.reason)).collect();
+        assert_eq!(rejected, [(4, RejectionReason::OutOfBand)]);
+    }
+
+    #[test]
+    fn update_targets() {
+        let samp_rate = 1_024_000;
+        let tag = |freq, period, first_pulse| TagConfig::new(freq, period, 0.5, first_pulse);
+        let tags = vec![tag(100_000.0, 0.9, 0.25), tag(-200_000.0, 1.1, 0.6), tag(300_000.0, 0.7, 0.15)];
+        let generator_config = GeneratorConfig::new(samp_rate, tags, 0.05, 17);
+        let (samples, truth) = Generator::new(generator_config).generate(10 * samp_rate as usize);
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let targets = [
+            tag_target(150_100_000.0),
+            PulseTarget { period: Some(1.1), period_variance: 0.01, ..tag_target(149_800_000.0) },
+        ];
+        let mut detectors = Detectors::new(&sdr_config, &targets);
+        detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+        // Replace the first target with a new target after 5 seconds, without changing the second
+        let (first, second) = samples.split_at(10 * samp_rate as usize);
+        let mut pulses: Vec<_> = first.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+        let new_target = PulseTarget { id: Some(5), ..tag_target(150_300_000.0) };
+        detectors.update_targets(&[PulseTarget { id: Some(1), ..targets[1].clone() }, new_target.clone()]);
+        assert!(detectors.rejected_targets().is_empty());
+        let ids: Vec<_> = detectors.targets().map(|x| x.0).collect();
+        assert_eq!(ids, [1, 5]);
+        let split = pulses.len();
+        pulses.extend(second.chunks(10_000).flat_map(|x| detectors.next_f32(x)));
+
+        // Each pulse is attributed to the right target
+        let switch_time = 5.0;
+        for &(tag, id) in &[(0, 0), (1, 1), (2, 5)] {
+            let expected: Vec<_> = truth.iter()
+                .filter(|x| x.tag == tag)
+                .map(|x| x.start as f64 / samp_rate as f64)
+                .filter(|&x| match id {
+                    0 => x < switch_time,
+                    5 => x > switch_time,
+                    _ => true,
+                })
+                .collect();
+            let detected: Vec<_> = pulses.iter()
+                .filter(|x| x.target_id == id)
+                .map(|x| x.timestamp.seconds as f64 + x.timestamp.nanos as f64 * 1e-9)
+                .collect();
+            assert_eq!(detected.len(), expected.len(), "target {}: {:?}", id, detected);
+            assert!(detected.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 0.002), "target {}", id);
+        }
+
+        // The unchanged target kept its pulse history
+        let next = pulses[split..].iter().find(|x| x.target_id == 1).unwrap();
+        assert!(next.period.map_or(false, |x| (x - 1.1).abs() < 0.01), "{:?}", next.period);
+
+        assert_eq!(detectors.add_target(5, new_target), Err(TargetError::DuplicateId(5)));
+        assert_eq!(detectors.remove_target(0), Err(TargetError::UnknownId(0)));
+        assert_eq!(
+            detectors.add_target(6, tag_target(151_000_000.0)),
+            Err(TargetError::OutOfBand(6)),
+        );
     }
 }
@@ -0,0 +1,360 @@
+//! The checks made on detected pulses against the pulses of other targets. Pulses are held until
+//! every target has had time to detect the same signal, then dropped if most targets detected a
+//! pulse at the same time (cross-target rejection), and flagged (or dropped) if they may be leakage
+//! of a stronger pulse from a target with a nearby frequency.
+
+use std::{cmp, iter};
+use std::collections::VecDeque;
+
+use common::{PulseFlag, PulseShape};
+
+use dsp_filters::pulse_detector::Pulse;
+use leakage::{self, LeakageConfig};
+
+/// The minimum number of targets that must detect a pulse at the same time for the pulses to be
+/// rejected as interference. Below this, simultaneous pulses are more likely to be real.
+const MIN_COINCIDENT_TARGETS: usize = 3;
+
+/// A pulse that has been detected, but not yet checked against the pulses of other targets
+#[derive(Copy, Clone, Debug)]
+pub struct Candidate {
+    pub target: usize,
+    pub pulse: Pulse,
+    pub shape: Option<PulseShape>,
+    pub start_sample: u64,
+
+    /// The window that the pulse was detected in
+    pub window: u64,
+}
+
+/// Holds detected pulses until they can be checked, and counts the pulses dropped by each check
+pub struct PulseChecks {
+    samp_rate: f32,
+    cross_target_fraction: Option<f32>,
+    leakage: Option<LeakageConfig>,
+
+    /// The window used by the frequency filter, for computing the leakage between targets
+    window_lut: Vec<f32>,
+
+    /// The maximum difference (in samples) between the starts of pulses detected at the same time
+    tolerance: u64,
+
+    /// The number of windows that pulses are held for before they are checked against the pulses
+    /// of other targets
+    release_delay: u64,
+
+    /// Detected pulses waiting to be checked against the pulses of other targets
+    pending: VecDeque<Candidate>,
+
+    /// Recently checked pulses
+    recent: VecDeque<Candidate>,
+
+    rejected_pulses: u64,
+    leakage_pulses: u64,
+}
+
+impl PulseChecks {
+    /// Create the checks for a filter using `window_lut`, where the cross-target rejection and
+    /// leakage checks are only made if they are configured.
+    pub fn new(
+        samp_rate: f32,
+        window_lut: Vec<f32>,
+        cross_target_fraction: Option<f32>,
+        leakage: Option<LeakageConfig>,
+    ) -> PulseChecks {
+        PulseChecks {
+            samp_rate,
+            cross_target_fraction,
+            leakage,
+            tolerance: window_lut.len() as u64,
+            window_lut,
+            release_delay: 0,
+            pending: VecDeque::new(),
+            recent: VecDeque::new(),
+            rejected_pulses: 0,
+            leakage_pulses: 0,
+        }
+    }
+
+    /// Whether pulses are compared across targets, in which case they need to be held for long
+    /// enough for every target to detect the same signal (see `set_release_delay`).
+    pub fn compares_targets(&self) -> bool {
+        self.cross_target_fraction.is_some() || self.leakage.is_some()
+    }
+
+    /// The number of windows that pulses are held for
+    pub fn release_delay(&self) -> u64 {
+        self.release_delay
+    }
+
+    pub fn set_release_delay(&mut self, release_delay: u64) {
+        self.release_delay = release_delay;
+    }
+
+    /// The number of pulses dropped by the cross-target and leakage checks respectively
+    pub fn dropped_pulses(&self) -> (u64, u64) {
+        (self.rejected_pulses, self.leakage_pulses)
+    }
+
+    /// Hold a newly detected pulse until it can be checked
+    pub fn push(&mut self, candidate: Candidate) {
+        self.pending.push_back(candidate);
+    }
+
+    /// Forget the pulses of a target that has been removed or changed
+    pub fn remove_target(&mut self, target: usize) {
+        self.pending.retain(|x| x.target != target);
+        self.recent.retain(|x| x.target != target);
+    }
+
+    /// Returns the pulses that have been held for long enough at `window_count`, and passed the
+    /// checks, along with the flag of each pulse. `freq` gives the frequency measured by the filter
+    /// for a target, and `num_targets` is the number of targets being detected.
+    pub fn release<F>(&mut self, window_count: u64, num_targets: usize, freq: F) -> Vec<(Candidate, Option<PulseFlag>)>
+        where F: Fn(usize) -> Option<f32>
+    {
+        let delay = self.release_delay;
+        let mut released = vec![];
+
+        while self.pending.front().map_or(false, |x| x.window + delay <= window_count) {
+            let candidate = self.pending.pop_front().unwrap();
+            let coincident = self.is_coincident(&candidate, num_targets);
+            let flag = self.leakage_source(&candidate, &freq)
+                .map(|(source, level)| PulseFlag::Leakage { source, level });
+            self.recent.push_back(candidate);
+
+            if coincident {
+                self.rejected_pulses += 1;
+            }
+            else if flag.is_some() && self.leakage.map_or(false, |x| x.suppress) {
+                self.leakage_pulses += 1;
+            }
+            else {
+                released.push((candidate, flag));
+            }
+        }
+
+        while self.recent.front().map_or(false, |x| x.window + 2 * delay < window_count) {
+            self.recent.pop_front();
+        }
+
+        released
+    }
+
+    /// Checks whether a pulse was detected at the same time (within one window) by most targets
+    fn is_coincident(&self, candidate: &Candidate, num_targets: usize) -> bool {
+        let fraction = match self.cross_target_fraction {
+            Some(fraction) => fraction,
+            None => return false,
+        };
+
+        let mut targets: Vec<_> = self.simultaneous_pulses(candidate)
+            .map(|x| x.target)
+            .chain(iter::once(candidate.target))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+
+        targets.len() >= MIN_COINCIDENT_TARGETS && targets.len() as f32 > fraction * num_targets as f32
+    }
+
+    /// Checks whether a pulse could be leakage of a stronger pulse detected at the same time by
+    /// another target, returning the target of the stronger pulse and the level of the expected
+    /// leakage relative to the pulse (in dB).
+    fn leakage_source<F>(&self, candidate: &Candidate, freq: F) -> Option<(usize, f32)>
+        where F: Fn(usize) -> Option<f32>
+    {
+        let config = self.leakage?;
+        let target_freq = freq(candidate.target)?;
+
+        self.simultaneous_pulses(candidate)
+            .filter(|x| x.target != candidate.target)
+            .filter_map(|x| freq(x.target).map(|source_freq| (x, source_freq)))
+            .map(|(x, source_freq)| {
+                let separation = (source_freq - target_freq) / self.samp_rate;
+                let leakage = x.pulse.max_signal_strength * leakage::window_response(&self.window_lut, separation);
+                (x.target, 20.0 * (leakage / candidate.pulse.max_signal_strength).log10())
+            })
+            // The level is not finite (and can't be compared) if the pulse has no signal strength
+            .filter(|x| x.1.is_finite() && x.1 > -config.margin)
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(cmp::Ordering::Equal))
+    }
+
+    /// The pulses of any target (other than `candidate` itself) detected within one window of
+    /// `candidate`
+    fn simultaneous_pulses<'a>(&'a self, candidate: &'a Candidate)
+        -> impl Iterator<Item = &'a Candidate> + 'a
+    {
+        let tolerance = self.tolerance;
+        self.pending.iter()
+            .chain(self.recent.iter())
+            .filter(move |x| (x.start_sample as i64 - candidate.start_sample as i64).abs() as u64 <= tolerance)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::{SdrConfig, Timestamp};
+    use dsp_filters::window::WindowType;
+    use evaluation;
+    use signal_gen::{add_pulse, tag_target, Generator, GeneratorConfig, TagConfig};
+    use DetectorsBuilder;
+
+    const SAMP_RATE: f32 = 1024.0;
+    const WINDOW_SIZE: usize = 64;
+
+    fn candidate(target: usize, strength: f32, start_sample: u64) -> Candidate {
+        let pulse = Pulse {
+            duration: 5,
+            max_signal_strength: strength,
+            mean_signal_strength: strength,
+            elapsed_samples: 0,
+            start_offset: 5,
+            noise_floor: None,
+        };
+        Candidate { target, pulse, shape: None, start_sample, window: 10 }
+    }
+
+    fn checks(cross_target_fraction: Option<f32>, leakage: Option<LeakageConfig>) -> PulseChecks {
+        let window_lut = WindowType::BlackmanHarris.generate_lut(WINDOW_SIZE);
+        let mut checks = PulseChecks::new(SAMP_RATE, window_lut, cross_target_fraction, leakage);
+        checks.set_release_delay(2);
+        checks
+    }
+
+    #[test]
+    fn rejects_coincident_pulses() {
+        let mut checks = checks(Some(0.5), None);
+        for target in 0..3 {
+            checks.push(candidate(target, 1.0, 1000 + target as u64));
+        }
+        checks.push(candidate(3, 1.0, 5000));
+
+        // Pulses are held for the release delay
+        assert!(checks.release(11, 4, |_| Some(0.0)).is_empty());
+
+        let released = checks.release(12, 4, |_| Some(0.0));
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0.target, 3);
+        assert_eq!(checks.dropped_pulses(), (3, 0));
+    }
+
+    #[test]
+    fn flags_leakage_from_nearby_target() {
+        let mut checks = checks(None, Some(LeakageConfig::default()));
+        checks.push(candidate(0, 1.0, 1000));
+        checks.push(candidate(1, 0.1, 1000));
+        checks.push(candidate(2, 0.0, 1000));
+
+        // Each bin is 16 Hz wide, so target 1 is in the main lobe of target 0
+        let freq = |id| Some(16.0 * id as f32);
+        let released = checks.release(12, 3, freq);
+        assert_eq!(released.len(), 3);
+        assert_eq!(released[0].1, None);
+        match released[1].1 {
+            Some(PulseFlag::Leakage { source, .. }) => assert_eq!(source, 0),
+            ref flag => panic!("{:?}", flag),
+        }
+
+        // A pulse without any signal strength can't be compared
+        assert_eq!(released[2].1, None);
+    }
+
+    #[test]
+    fn rejects_broadband_interference() {
+        let samp_rate = 1_024_000;
+        let tags = vec![TagConfig::new(100_000.0, 0.9, 0.5, 0.25), TagConfig::new(-200_000.0, 0.9, 0.5, 0.6)];
+        let generator_config = GeneratorConfig::new(samp_rate, tags, 0.05, 5);
+        let (mut samples, truth) = Generator::new(generator_config).generate(10 * samp_rate as usize);
+
+        // Broadband interference as long as a tag pulse appears in every target bin at once
+        let offsets = [100_000.0, -200_000.0, 300_000.0, -400_000.0];
+        for k in 0..20 {
+            let start = (0.4 + 0.45 * k as f32) * samp_rate as f32;
+            for &freq in &offsets {
+                let end = start as usize + (0.02 * samp_rate as f32) as usize;
+                add_pulse(&mut samples, samp_rate as f32, freq, start as usize, end);
+            }
+        }
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let targets: Vec<_> = offsets.iter().map(|x| tag_target(150_000_000.0 + x)).collect();
+
+        let mut results = vec![];
+        for &rejection in &[false, true] {
+            let mut builder = DetectorsBuilder::new();
+            if rejection {
+                builder = builder.cross_target_rejection(0.5);
+            }
+            let mut detectors = builder.build(&sdr_config, &targets);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = samples.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+            let evaluation = evaluation::evaluate(&truth, &pulses, samp_rate, 10.0, 0.002);
+            results.push((evaluation, detectors.stats()));
+        }
+
+        let (ref unchecked, _) = results[0];
+        let (ref checked, stats) = results[1];
+        assert_eq!(unchecked.false_alarms, 80, "{:?}", unchecked);
+        assert_eq!(checked.detected, truth.len(), "{:?}", checked);
+        assert_eq!(checked.false_alarms, 0, "{:?}", checked);
+        assert_eq!(stats.rejected_pulses, 80);
+    }
+
+    #[test]
+    fn flags_or_drops_leakage() {
+        // A strong tag 1.5 kHz (1.5 bins) from a weak tag
+        let samp_rate = 1_024_000;
+        let tags = vec![TagConfig::new(100_000.0, 0.9, 2.0, 0.25), TagConfig::new(101_500.0, 0.9, 0.3, 0.6)];
+        let generator_config = GeneratorConfig::new(samp_rate, tags, 0.05, 13);
+        let (samples, truth) = Generator::new(generator_config).generate(10 * samp_rate as usize);
+
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let targets = [tag_target(150_100_000.0), tag_target(150_101_500.0)];
+
+        let run = |config: Option<LeakageConfig>| {
+            let mut builder = DetectorsBuilder::new();
+            if let Some(config) = config {
+                builder = builder.leakage_check(config);
+            }
+            let mut detectors = builder.build(&sdr_config, &targets);
+            detectors.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+            let pulses: Vec<_> = samples.chunks(10_000).flat_map(|x| detectors.next_f32(x)).collect();
+            (pulses, detectors.stats())
+        };
+
+        // Every pulse of the strong tag is also detected by the weak tag's target
+        let (pulses, _) = run(None);
+        let unchecked = evaluation::evaluate(&truth, &pulses, samp_rate, 10.0, 0.002);
+        let strong_pulses = truth.iter().filter(|x| x.tag == 0).count();
+        assert_eq!(unchecked.false_alarms, strong_pulses, "{:?}", unchecked);
+
+        // The leakage is flagged, with the target it came from
+        let (pulses, _) = run(Some(LeakageConfig::default()));
+        let (flagged, unflagged): (Vec<_>, Vec<_>) = pulses.into_iter().partition(|x| x.flag.is_some());
+        assert_eq!(flagged.len(), strong_pulses);
+        for pulse in &flagged {
+            assert_eq!(pulse.target_id, 1);
+            match pulse.flag {
+                Some(PulseFlag::Leakage { source, level }) => {
+                    assert_eq!(source, 0);
+                    assert!(level.abs() < 3.0, "{}", level);
+                }
+                ref flag => panic!("unexpected flag: {:?}", flag),
+            }
+        }
+        let evaluation = evaluation::evaluate(&truth, &unflagged, samp_rate, 10.0, 0.002);
+        assert_eq!(evaluation.detected, truth.len(), "{:?}", evaluation);
+        assert_eq!(evaluation.false_alarms, 0, "{:?}", evaluation);
+
+        // Or dropped
+        let (pulses, stats) = run(Some(LeakageConfig { suppress: true, ..LeakageConfig::default() }));
+        let evaluation = evaluation::evaluate(&truth, &pulses, samp_rate, 10.0, 0.002);
+        assert_eq!(evaluation.detected, truth.len(), "{:?}", evaluation);
+        assert_eq!(evaluation.false_alarms, 0, "{:?}", evaluation);
+        assert_eq!(stats.leakage_pulses as usize, strong_pulses);
+    }
+}
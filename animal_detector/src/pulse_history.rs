@@ -0,0 +1,248 @@
+//! Tracks the intervals between pulses detected for a single target.
+
+use std::collections::VecDeque;
+
+use common::TagStatus;
+
+/// The maximum number of recent pulses to keep for each target
+const MAX_HISTORY: usize = 16;
+
+/// The maximum number of consecutive missed pulses allowed when matching a pulse to the expected
+/// period
+const MAX_MISSED_PULSES: u64 = 4;
+
+/// The number of recent intervals used to classify the status of a target
+const STATUS_INTERVALS: usize = 5;
+
+/// The minimum number of intervals required to classify the status of a target
+const MIN_STATUS_INTERVALS: usize = 3;
+
+#[derive(Copy, Clone, Debug)]
+pub struct PeriodConfig {
+    /// The expected number of samples between consecutive pulses
+    pub period: u64,
+
+    /// The expected number of samples between consecutive pulses when the tag is in mortality
+    /// mode
+    pub mortality_period: Option<u64>,
+
+    /// The maximum allowed variance in the period (number of samples)
+    pub variance: u64,
+}
+
+impl PeriodConfig {
+    /// Checks whether `elapsed` samples matches a whole number of `period`s, returning the number
+    /// of periods if it does.
+    fn matches(&self, elapsed: u64, period: u64) -> Option<u64> {
+        let periods = missed_periods(elapsed, period);
+        let error = (elapsed as i64 - (periods * period) as i64).abs() as u64;
+        if periods >= 1 && periods <= MAX_MISSED_PULSES && error <= periods * self.variance {
+            return Some(periods);
+        }
+        None
+    }
+
+    /// The longest expected period of the target
+    fn max_period(&self) -> u64 {
+        self.period.max(self.mortality_period.unwrap_or(0))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Interval {
+    /// The measured number of samples between consecutive pulses
+    pub period: u64,
+
+    /// Whether the interval is consistent with one of the expected periods. Always true if there
+    /// is no expected period.
+    pub consistent: bool,
+}
+
+pub struct PulseHistory {
+    /// The expected period of the target
+    config: Option<PeriodConfig>,
+
+    /// The start sample of recently detected pulses, including pulses that were inconsistent with
+    /// the expected period.
+    recent: VecDeque<u64>,
+
+    /// The most recent intervals that were consistent with one of the expected periods
+    intervals: VecDeque<u64>,
+
+    /// The median of `intervals`
+    median: Option<u64>,
+}
+
+impl PulseHistory {
+    pub fn new(config: Option<PeriodConfig>) -> PulseHistory {
+        PulseHistory {
+            config,
+            recent: VecDeque::with_capacity(MAX_HISTORY),
+            intervals: VecDeque::with_capacity(STATUS_INTERVALS),
+            median: None,
+        }
+    }
+
+    /// Records a pulse starting at `sample`, returning the interval since the previous pulse or
+    /// `None` if this is the first pulse.
+    ///
+    /// When an expected period is configured, the pulse is compared to every recent pulse so that
+    /// spurious pulses between two real pulses do not prevent a match, and up to
+    /// `MAX_MISSED_PULSES` missed pulses are tolerated.
+    pub fn record(&mut self, sample: u64) -> Option<Interval> {
+        let interval = self.interval(sample);
+
+        if self.recent.len() == MAX_HISTORY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(sample);
+
+        if let Some(interval) = interval {
+            if interval.consistent && self.config.is_some() {
+                if self.intervals.len() == STATUS_INTERVALS {
+                    self.intervals.pop_front();
+                }
+                self.intervals.push_back(interval.period);
+                self.update_median();
+            }
+        }
+
+        interval
+    }
+
+    /// The median of the most recent intervals that were consistent with an expected period
+    pub fn median_interval(&self) -> Option<u64> {
+        self.median
+    }
+
+    fn update_median(&mut self) {
+        if self.intervals.len() < MIN_STATUS_INTERVALS {
+            self.median = None;
+            return;
+        }
+
+        let mut intervals: Vec<_> = self.intervals.iter().cloned().collect();
+        intervals.sort();
+        self.median = Some(intervals[intervals.len() / 2]);
+    }
+
+    /// Classifies the current behavior of the target based on the observed pulse rate. `sample` is
+    /// the current sample index, and is used to detect targets that are no longer being heard.
+    pub fn status(&self, sample: u64) -> TagStatus {
+        let config = match self.config {
+            Some(config) => config,
+            None => return TagStatus::Unknown,
+        };
+
+        let last = match self.recent.back() {
+            Some(&last) => last,
+            None => return TagStatus::Unknown,
+        };
+        if sample.saturating_sub(last) > (MAX_MISSED_PULSES + 1) * config.max_period() {
+            return TagStatus::Unknown;
+        }
+
+        match self.median_interval() {
+            Some(x) if config.matches(x, config.period) == Some(1) => TagStatus::Normal,
+            Some(x) if config.mortality_period.and_then(|p| config.matches(x, p)) == Some(1) => {
+                TagStatus::Mortality
+            }
+            _ => TagStatus::Unknown,
+        }
+    }
+
+    fn interval(&self, sample: u64) -> Option<Interval> {
+        let last = *self.recent.back()?;
+        let elapsed = sample.saturating_sub(last);
+
+        let config = match self.config {
+            Some(config) if config.period > 0 => config,
+            _ => return Some(Interval { period: elapsed, consistent: true }),
+        };
+
+        let periods = [Some(config.period), config.mortality_period];
+        for &prev in self.recent.iter().rev() {
+            let elapsed = sample.saturating_sub(prev);
+            if elapsed > (MAX_MISSED_PULSES + 1) * config.max_period() {
+                break;
+            }
+
+            // Prefer the period that requires the fewest missed pulses
+            let best = periods.iter()
+                .filter_map(|&period| period)
+                .filter_map(|period| config.matches(elapsed, period))
+                .min();
+
+            if let Some(periods) = best {
+                return Some(Interval { period: elapsed / periods, consistent: true });
+            }
+        }
+
+        let periods = missed_periods(elapsed, config.period).max(1);
+        Some(Interval { period: elapsed / periods, consistent: false })
+    }
+}
+
+/// The number of periods that are closest to `elapsed` samples
+fn missed_periods(elapsed: u64, period: u64) -> u64 {
+    (elapsed + period / 2) / period.max(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignores_spurious_and_missed_pulses() {
+        let mut history = PulseHistory::new(Some(PeriodConfig {
+            period: 100,
+            mortality_period: None,
+            variance: 5,
+        }));
+
+        assert!(history.record(1000).is_none());
+
+        let interval = history.record(1102).unwrap();
+        assert!(interval.consistent);
+        assert_eq!(interval.period, 102);
+
+        // A spurious pulse, followed by a real pulse
+        assert!(!history.record(1150).unwrap().consistent);
+        let interval = history.record(1199).unwrap();
+        assert!(interval.consistent);
+        assert_eq!(interval.period, 97);
+
+        // Two missed pulses
+        let interval = history.record(1500).unwrap();
+        assert!(interval.consistent);
+        assert_eq!(interval.period, 100);
+    }
+
+    #[test]
+    fn mortality_status() {
+        let mut history = PulseHistory::new(Some(PeriodConfig {
+            period: 100,
+            mortality_period: Some(40),
+            variance: 3,
+        }));
+        assert_eq!(history.status(0), TagStatus::Unknown);
+
+        let mut sample = 0;
+        for _ in 0..5 {
+            sample += 100;
+            history.record(sample);
+        }
+        assert_eq!(history.status(sample), TagStatus::Normal);
+
+        // Switch to the mortality pulse rate, the status changes once most recent intervals match
+        for i in 0..5 {
+            sample += 40;
+            history.record(sample);
+            let expected = if i < 2 { TagStatus::Normal } else { TagStatus::Mortality };
+            assert_eq!(history.status(sample), expected);
+        }
+
+        // The target has not been heard for a long time
+        assert_eq!(history.status(sample + 1000), TagStatus::Unknown);
+    }
+}
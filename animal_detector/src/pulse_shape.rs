@@ -0,0 +1,176 @@
+//! Measures the shape of the magnitude envelope of detected pulses, for rejecting interference
+//! that does not look like a keyed CW tag pulse.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::f32;
+
+use common::PulseShape;
+
+/// Keeps the recent signal strength values of a single target, so that the envelope of a pulse
+/// can be measured once it has been detected.
+pub struct Envelope {
+    /// The signal strength values of the most recent windows
+    history: VecDeque<f32>,
+
+    /// The maximum number of windows to keep in `history`
+    history_length: usize,
+
+    /// The rate (in Hz) of the signal strength values
+    samp_rate: f32,
+}
+
+impl Envelope {
+    /// Create a new envelope, where `history` is the number of windows between the start of a
+    /// pulse and its detection that need to be kept, not including the signal before the pulse.
+    pub fn new(samp_rate: f32, history: usize) -> Envelope {
+        Envelope {
+            history: VecDeque::with_capacity(2 * history + 1),
+            history_length: 2 * history + 1,
+            samp_rate,
+        }
+    }
+
+    /// Discard the signal strength of previous windows
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Add the signal strength of the current window
+    pub fn input(&mut self, value: f32) {
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(value);
+    }
+
+    /// Measure the shape of a pulse that started `start_offset` windows ago and lasted for
+    /// `duration` windows. Returns `None` if the pulse is too short, or not enough of the signal
+    /// either side of it has been kept.
+    pub fn shape(&self, start_offset: usize, duration: usize) -> Option<PulseShape> {
+        let newest = self.history.len().checked_sub(1)?;
+        let start = newest.checked_sub(start_offset)?;
+        let end = start + duration;
+        if duration < 3 || end > newest {
+            return None;
+        }
+
+        // Compare the pulse to up to half a pulse length of signal on either side, skipping the
+        // windows next to the pulse which may contain part of an edge
+        let margin = (duration / 2).max(3);
+        let before = start.saturating_sub(margin);
+        let after = (end + margin).min(newest + 1);
+        if start - before < 3 {
+            return None;
+        }
+
+        let values: Vec<f32> = self.history.iter().cloned().collect();
+        let on = &values[start..end];
+        let off_after = values.get(end + 1..after).unwrap_or(&[]);
+        let off: Vec<f32> = values[before..start - 1].iter().chain(off_after).cloned().collect();
+
+        let mut sorted = on.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let on_level = sorted[sorted.len() / 2];
+        let off_level = mean(&off);
+        let range = on_level - off_level;
+        if range <= 0.0 {
+            return None;
+        }
+        let low = off_level + 0.1 * range;
+        let high = off_level + 0.9 * range;
+
+        // The edges are measured from the first (or last) window above each level, searching from
+        // outside of the pulse towards its middle.
+        let middle = start + duration / 2;
+        let rise = &values[before..=middle];
+        let rise_time = edge_length(rise.iter(), low, high);
+        let fall = &values[middle..after];
+        let fall_time = edge_length(fall.iter().rev(), low, high);
+
+        // The edges are excluded when measuring the flatness of the pulse
+        let trim = duration / 10;
+        let top = &on[trim..duration - trim];
+        let top_mean = mean(top);
+        let deviation = (top.iter().map(|x| (x - top_mean).powi(2)).sum::<f32>() / top.len() as f32).sqrt();
+        let flatness = if top_mean > 0.0 { 1.0 - deviation / top_mean } else { 0.0 };
+
+        let on_energy = on.iter().map(|x| x * x).sum::<f32>() / on.len() as f32;
+        let off_energy = off.iter().map(|x| x * x).sum::<f32>() / off.len() as f32;
+        let on_off_ratio = 10.0 * (on_energy.max(f32::MIN_POSITIVE) / off_energy.max(f32::MIN_POSITIVE)).log10();
+
+        Some(PulseShape {
+            rise_time: rise_time as f32 / self.samp_rate,
+            fall_time: fall_time as f32 / self.samp_rate,
+            flatness,
+            on_off_ratio,
+        })
+    }
+}
+
+/// The number of windows between the first value above `low` and the first value above `high`
+fn edge_length<'a, I: Iterator<Item = &'a f32> + Clone>(values: I, low: f32, high: f32) -> usize {
+    let first_above = |level: f32| values.clone().position(|&x| x >= level);
+    match (first_above(low), first_above(high)) {
+        (Some(low), Some(high)) => high.saturating_sub(low),
+        _ => 0,
+    }
+}
+
+fn mean(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn measure(signal: &[f32], start_offset: usize, duration: usize) -> PulseShape {
+        let mut envelope = Envelope::new(1000.0, 40);
+        for &x in signal {
+            envelope.input(x);
+        }
+        envelope.shape(start_offset, duration).unwrap()
+    }
+
+    #[test]
+    fn keyed_pulse() {
+        // A 20 window pulse with a one window edge either side, detected 9 windows after it ended
+        let mut signal = vec![0.1; 50];
+        signal[19] = 0.5;
+        signal[20..40].iter_mut().for_each(|x| *x = 1.0);
+        signal[40] = 0.5;
+
+        let shape = measure(&signal, 29, 20);
+        assert!(shape.rise_time <= 0.001, "{:?}", shape);
+        assert!(shape.fall_time <= 0.001, "{:?}", shape);
+        assert!(shape.flatness > 0.99, "{:?}", shape);
+        assert!((shape.on_off_ratio - 20.0).abs() < 1.0, "{:?}", shape);
+    }
+
+    #[test]
+    fn ramped_pulse() {
+        // A pulse that ramps up over its whole length, like a swept carrier passing through the bin
+        let mut signal = vec![0.1; 50];
+        for i in 0..20 {
+            signal[20 + i] = 0.1 + 0.9 * i as f32 / 19.0;
+        }
+
+        let shape = measure(&signal, 29, 20);
+        assert!(shape.rise_time >= 0.005, "{:?}", shape);
+        assert!(shape.flatness < 0.8, "{:?}", shape);
+    }
+
+    #[test]
+    fn not_enough_history() {
+        let mut envelope = Envelope::new(1000.0, 10);
+        for _ in 0..5 {
+            envelope.input(1.0);
+        }
+        assert!(envelope.shape(4, 4).is_none());
+        assert!(envelope.shape(10, 4).is_none());
+    }
+}
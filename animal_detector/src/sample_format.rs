@@ -0,0 +1,74 @@
+//! Conversion of raw I/Q samples into floating point values.
+
+use std::io::{self, Read};
+
+pub use common::SampleFormat;
+
+use util;
+
+/// Converts an unsigned 8-bit sample (offset by 128), e.g. from an RTL-SDR
+#[inline]
+pub fn convert_cu8(i: u8, q: u8) -> (f32, f32) {
+    // Offset binary is two's complement with the sign bit inverted
+    util::convert_iq(i ^ 0x80, q ^ 0x80)
+}
+
+/// Converts a signed 8-bit sample, e.g. from a HackRF
+#[inline]
+pub fn convert_cs8(i: u8, q: u8) -> (f32, f32) {
+    util::convert_iq(i, q)
+}
+
+/// Converts a signed 16-bit sample
+#[inline]
+pub fn convert_cs16(i: i16, q: i16) -> (f32, f32) {
+    (i as f32 / 32768.0, q as f32 / 32768.0)
+}
+
+/// Returns a function that converts the raw bytes of a single sample in `format`. Multi-byte
+/// formats are little endian.
+pub fn byte_converter(format: SampleFormat) -> fn(&[u8]) -> (f32, f32) {
+    match format {
+        SampleFormat::Cu8 => |x| convert_cu8(x[0], x[1]),
+        SampleFormat::Cs8 => |x| convert_cs8(x[0], x[1]),
+        SampleFormat::Cs16 => {
+            |x| convert_cs16(i16::from_le_bytes([x[0], x[1]]), i16::from_le_bytes([x[2], x[3]]))
+        }
+        SampleFormat::Cf32 => |x| {
+            let i = u32::from_le_bytes([x[0], x[1], x[2], x[3]]);
+            let q = u32::from_le_bytes([x[4], x[5], x[6], x[7]]);
+            (f32::from_bits(i), f32::from_bits(q))
+        },
+    }
+}
+
+/// Read as many whole samples in `format` from `input` as will fit in `buffer`, returning the number
+/// of bytes read. Fewer bytes are only returned at the end of the input, and any trailing partial
+/// sample is discarded.
+pub fn read_samples<R: Read>(input: &mut R, buffer: &mut [u8], format: SampleFormat) -> io::Result<usize> {
+    let mut length = 0;
+    while length < buffer.len() {
+        match input.read(&mut buffer[length..]) {
+            Ok(0) => break,
+            Ok(n) => length += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(length - length % format.sample_size())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_bytes() {
+        assert_eq!(byte_converter(SampleFormat::Cu8)(&[128, 64]), (0.0, -0.5));
+        assert_eq!(byte_converter(SampleFormat::Cs8)(&[0, 192]), (0.0, -0.5));
+        assert_eq!(byte_converter(SampleFormat::Cs16)(&[0x00, 0x40, 0x00, 0xc0]), (0.5, -0.5));
+
+        let bytes: Vec<_> = [0.25_f32, -1.0].iter().flat_map(|x| x.to_bits().to_le_bytes().to_vec()).collect();
+        assert_eq!(byte_converter(SampleFormat::Cf32)(&bytes), (0.25, -1.0));
+    }
+}
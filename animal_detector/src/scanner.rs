@@ -0,0 +1,295 @@
+//! Scans the entire bandwidth of the SDR for pulses from targets with unknown frequencies.
+
+use num_complex::Complex;
+
+use common::{SdrConfig, ScanCandidate, ScanConfig, ScanResult, Timestamp};
+use dsp_filters::fft::Fft;
+use dsp_filters::pulse_detector::{PulseDetector, DetectorConfig};
+use dsp_filters::window::WindowType;
+use freq_filter::DEFAULT_WINDOW_SIZE;
+use sample_format::{self, SampleFormat};
+use util;
+use DEFAULT_NOISE_WINDOW;
+
+/// The rising and falling edges of a strong pulse spread energy across the entire band, which
+/// causes weak pulses to be detected in every bin at the same time. Pulses that are weaker than
+/// this fraction of the strongest pulse detected in the same frame are ignored.
+const SPLATTER_RATIO: f32 = 0.1;
+
+/// The pulse detector and pulse statistics for a single frequency bin
+struct Bin {
+    detector: PulseDetector,
+    pulse_count: usize,
+    total_duration: i64,
+    max_signal_strength: f32,
+}
+
+impl Bin {
+    fn reset(&mut self) {
+        self.pulse_count = 0;
+        self.total_duration = 0;
+        self.max_signal_strength = 0.0;
+    }
+}
+
+/// Detects pulses in every frequency bin of an FFT spanning the full bandwidth of the SDR, and
+/// periodically reports the frequencies where pulses were found.
+pub struct Scanner {
+    samp_rate: u64,
+    center_freq: f32,
+    fft: Fft,
+    window_lut: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    sample_count: usize,
+    frame_count: u64,
+    frames_per_report: u64,
+    start_time: Option<Timestamp>,
+    sample_format: SampleFormat,
+    bins: Vec<Bin>,
+
+    /// The pulses detected in the current frame as (bin, duration, signal strength)
+    detections: Vec<(usize, i64, f32)>,
+}
+
+impl Scanner {
+    pub fn new(sdr_config: &SdrConfig, config: &ScanConfig) -> Scanner {
+        let size = DEFAULT_WINDOW_SIZE;
+        let frame_rate = sdr_config.samp_rate as f32 / size as f32;
+
+        let min_duration = config.min_duration * frame_rate;
+        let max_duration = config.max_duration * frame_rate;
+        let detector_config = DetectorConfig {
+            samp_rate: frame_rate,
+            duration: ((min_duration + max_duration) / 2.0) as i64,
+            duration_variance: ((max_duration - min_duration) / 2.0).ceil() as i64,
+            threshold: 0.0,
+            noise_window: (DEFAULT_NOISE_WINDOW * frame_rate) as i64,
+            cfar_multiplier: Some(config.multiplier),
+            edge_length: config.edge_length,
+            peak_lookahead: config.peak_lookahead,
+        };
+
+        let bins = (0..size)
+            .map(|_| Bin {
+                detector: PulseDetector::new(detector_config),
+                pulse_count: 0,
+                total_duration: 0,
+                max_signal_strength: 0.0,
+            })
+            .collect();
+
+        Scanner {
+            samp_rate: sdr_config.samp_rate,
+            center_freq: sdr_config.center_freq as f32,
+            fft: Fft::new(size),
+            window_lut: WindowType::BlackmanHarris.generate_lut(size),
+            buffer: vec![Complex::new(0.0, 0.0); size],
+            sample_count: 0,
+            frame_count: 0,
+            frames_per_report: ((config.report_interval * frame_rate) as u64).max(1),
+            start_time: None,
+            sample_format: sdr_config.sample_format,
+            bins,
+            detections: vec![],
+        }
+    }
+
+    /// Sets the time of the first sample in the stream. If this is not set, the start time is
+    /// anchored when the first buffer of samples is received.
+    pub fn set_start_time(&mut self, start_time: Timestamp) {
+        self.start_time = Some(start_time);
+    }
+
+    /// Process the next set of samples (in signed 8-bit, I/Q format), returning any completed
+    /// reports.
+    pub fn next(&mut self, samples: &[u8]) -> Vec<ScanResult> {
+        self.next_buffer(samples, 2, |x| sample_format::convert_cs8(x[0], x[1]))
+    }
+
+    /// Process the next set of samples (in unsigned 8-bit, I/Q format), returning any completed
+    /// reports.
+    pub fn next_cu8(&mut self, samples: &[u8]) -> Vec<ScanResult> {
+        self.next_buffer(samples, 2, |x| sample_format::convert_cu8(x[0], x[1]))
+    }
+
+    /// Process the next set of samples (in signed 16-bit, I/Q format), returning any completed
+    /// reports.
+    pub fn next_cs16(&mut self, samples: &[i16]) -> Vec<ScanResult> {
+        self.next_buffer(samples, 2, |x| sample_format::convert_cs16(x[0], x[1]))
+    }
+
+    /// Process the next set of samples (in float32, I/Q format), returning any completed reports.
+    pub fn next_f32(&mut self, samples: &[f32]) -> Vec<ScanResult> {
+        self.next_buffer(samples, 2, |x| (x[0], x[1]))
+    }
+
+    /// Process the next set of raw samples in the format given by `SdrConfig::sample_format`,
+    /// returning any completed reports.
+    pub fn next_bytes(&mut self, samples: &[u8]) -> Vec<ScanResult> {
+        let convert = sample_format::byte_converter(self.sample_format);
+        self.next_buffer(samples, self.sample_format.sample_size(), convert)
+    }
+
+    fn next_buffer<T, F>(&mut self, samples: &[T], sample_len: usize, convert: F) -> Vec<ScanResult>
+        where F: Fn(&[T]) -> (f32, f32)
+    {
+        if self.start_time.is_none() {
+            let num_samples = (samples.len() / sample_len) as u64;
+            let buffer_nanos = util::samples_to_nanos(num_samples, self.samp_rate);
+            self.start_time = Some(Timestamp::now().sub_nanos(buffer_nanos));
+        }
+
+        let mut results = vec![];
+
+        for sample in samples.chunks(sample_len) {
+            let (i, q) = convert(sample);
+            self.buffer[self.sample_count] = Complex::new(i, q) * self.window_lut[self.sample_count];
+            self.sample_count += 1;
+
+            if self.sample_count == self.buffer.len() {
+                self.sample_count = 0;
+                if let Some(result) = self.process_frame() {
+                    results.push(result);
+                }
+            }
+        }
+
+        results
+    }
+
+    fn process_frame(&mut self) -> Option<ScanResult> {
+        self.fft.process(&mut self.buffer);
+
+        self.detections.clear();
+        for (k, (bin, value)) in self.bins.iter_mut().zip(&self.buffer).enumerate() {
+            if let Some(pulse) = bin.detector.input(value.norm()) {
+                self.detections.push((k, pulse.duration, pulse.max_signal_strength));
+            }
+        }
+
+        let strongest = self.detections.iter().fold(0.0_f32, |acc, x| acc.max(x.2));
+        for &(k, duration, signal_strength) in &self.detections {
+            if signal_strength >= SPLATTER_RATIO * strongest {
+                let bin = &mut self.bins[k];
+                bin.pulse_count += 1;
+                bin.total_duration += duration;
+                bin.max_signal_strength = bin.max_signal_strength.max(signal_strength);
+            }
+        }
+
+        self.frame_count += 1;
+        if self.frame_count % self.frames_per_report != 0 {
+            return None;
+        }
+
+        let start_time = self.start_time.unwrap_or(Timestamp { seconds: 0, nanos: 0 });
+        let end_sample = self.frame_count * self.buffer.len() as u64;
+
+        let result = ScanResult {
+            candidates: self.candidates(),
+            timestamp: start_time.add_nanos(util::samples_to_nanos(end_sample, self.samp_rate)),
+        };
+
+        for bin in &mut self.bins {
+            bin.reset();
+        }
+
+        Some(result)
+    }
+
+    /// Find the frequencies where pulses were detected. A strong pulse is detected in several
+    /// adjacent bins, so adjacent bins with pulses are merged into a single candidate located at
+    /// the strongest bin.
+    fn candidates(&self) -> Vec<ScanCandidate> {
+        let size = self.bins.len();
+        let bin_width = self.samp_rate as f32 / size as f32;
+        let frame_rate = bin_width;
+
+        // Order the bins from the lowest to the highest frequency
+        let ordered = (size / 2..size).chain(0..size / 2).map(|k| {
+            let offset = if k >= size / 2 { k as f32 - size as f32 } else { k as f32 };
+            (offset, &self.bins[k])
+        });
+
+        let mut candidates = vec![];
+        let mut best: Option<(f32, &Bin)> = None;
+
+        for (offset, bin) in ordered {
+            if bin.pulse_count == 0 {
+                candidates.extend(best.take());
+                continue;
+            }
+
+            match best {
+                Some((_, current)) if current.max_signal_strength >= bin.max_signal_strength => {}
+                _ => best = Some((offset, bin)),
+            }
+        }
+        candidates.extend(best.take());
+
+        candidates.into_iter()
+            .map(|(offset, bin)| ScanCandidate {
+                freq: self.center_freq + offset * bin_width,
+                pulse_count: bin.pulse_count,
+                duration: bin.total_duration as f32 / bin.pulse_count as f32 / frame_rate,
+                signal_strength: bin.max_signal_strength,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn finds_unknown_targets() {
+        let samp_rate = 1_024_000;
+        let sdr_config = SdrConfig { samp_rate, center_freq: 150_000_000, ..SdrConfig::default() };
+        let config = ScanConfig {
+            min_duration: 0.015,
+            max_duration: 0.025,
+            multiplier: 5.0,
+            edge_length: 3,
+            peak_lookahead: 2,
+            report_interval: 1.0,
+        };
+        let mut scanner = Scanner::new(&sdr_config, &config);
+        scanner.set_start_time(Timestamp { seconds: 0, nanos: 0 });
+
+        // Low level noise from a simple linear congruential generator
+        let mut state = 1_u32;
+        let mut signal: Vec<f32> = (0..3 * samp_rate as usize * 2)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) as f32 / 65536.0 - 0.5) * 0.1
+            })
+            .collect();
+
+        // 20 ms pulses every 200 ms from two tags
+        for &(freq, first) in &[(100_000.0, 10_240), (-200_000.0, 61_440)] {
+            for start in (first..3 * samp_rate as usize).step_by(204_800) {
+                for n in start..(start + 20_480).min(3 * samp_rate as usize) {
+                    let phase = 2.0 * PI * freq * n as f32 / samp_rate as f32;
+                    signal[2 * n] += phase.cos();
+                    signal[2 * n + 1] += phase.sin();
+                }
+            }
+        }
+
+        let results = scanner.next_f32(&signal);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[2].timestamp.seconds, 3);
+
+        // The noise floor is established by the last report
+        let candidates = &results[2].candidates;
+        assert_eq!(candidates.len(), 2, "{:?}", candidates);
+        assert_eq!(candidates[0].freq, 149_800_000.0);
+        assert_eq!(candidates[1].freq, 150_100_000.0);
+        for candidate in candidates {
+            assert_eq!(candidate.pulse_count, 5);
+            assert!((candidate.duration - 0.02).abs() < 0.003, "{:?}", candidate);
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Support for recordings in the SigMF format (https://github.com/gnuradio/SigMF). A recording
+//! consists of a `.sigmf-meta` JSON file describing the capture, and a `.sigmf-data` file
+//! containing the raw samples.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use common::{Pulse, PulseFlag, SdrConfig, Timestamp};
+use sample_format::SampleFormat;
+use util;
+
+pub const META_EXTENSION: &str = "sigmf-meta";
+pub const DATA_EXTENSION: &str = "sigmf-data";
+
+/// The version of the SigMF specification used for new recordings
+pub const VERSION: &str = "0.0.2";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    pub global: Global,
+    pub captures: Vec<Capture>,
+
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Global {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+
+    #[serde(rename = "core:sample_rate")]
+    pub sample_rate: f64,
+
+    #[serde(rename = "core:version")]
+    pub version: String,
+
+    /// Any other fields (e.g. `core:description`), preserved when metadata is rewritten
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Capture {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+
+    #[serde(rename = "core:frequency", default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+
+    /// The time of the first sample of the capture as an ISO 8601 string
+    #[serde(rename = "core:datetime", default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<String>,
+}
+
+impl Capture {
+    /// The time of the first sample of the capture
+    pub fn start_time(&self) -> Option<Timestamp> {
+        self.datetime.as_ref().and_then(|x| parse_datetime(x))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+
+    #[serde(rename = "core:sample_count")]
+    pub sample_count: u64,
+
+    #[serde(rename = "core:freq_lower_edge", default, skip_serializing_if = "Option::is_none")]
+    pub freq_lower_edge: Option<f64>,
+
+    #[serde(rename = "core:freq_upper_edge", default, skip_serializing_if = "Option::is_none")]
+    pub freq_upper_edge: Option<f64>,
+
+    #[serde(rename = "core:label", default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    #[serde(rename = "core:comment", default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl Metadata {
+    /// Create the metadata for a new recording made with `sdr_config`, where the first sample was
+    /// captured at `start_time`.
+    pub fn new(sdr_config: &SdrConfig, start_time: Timestamp) -> Metadata {
+        Metadata {
+            global: Global {
+                datatype: format_datatype(sdr_config.sample_format).into(),
+                sample_rate: sdr_config.samp_rate as f64,
+                version: VERSION.into(),
+                extra: serde_json::Map::new(),
+            },
+            captures: vec![Capture {
+                sample_start: 0,
+                frequency: Some(sdr_config.center_freq as f64),
+                datetime: Some(format_datetime(start_time)),
+            }],
+            annotations: vec![],
+        }
+    }
+
+    /// Read the metadata of a recording. `path` can be either the metadata or the data file.
+    pub fn read(path: &Path) -> io::Result<Metadata> {
+        let file = File::open(meta_path(path))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write the metadata of a recording. `path` can be either the metadata or the data file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(meta_path(path))?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+
+    /// The time of the first sample in the recording
+    pub fn start_time(&self) -> Option<Timestamp> {
+        self.captures.first().and_then(|x| x.start_time())
+    }
+
+    /// Add an annotation marking the location of a detected pulse. The pulse's timestamp is
+    /// converted to a sample index using the start time of the recording, or treated as relative
+    /// to the start of the recording if the start time is unknown.
+    pub fn add_pulse(&mut self, pulse: &Pulse) {
+        let start_time = self.start_time().unwrap_or(Timestamp { seconds: 0, nanos: 0 });
+        let samp_rate = self.global.sample_rate;
+
+        let offset = util::nanos_between(start_time, pulse.timestamp);
+        let sample_start = (offset as f64 * samp_rate / 1e9).round() as u64;
+        let sample_count = (pulse.duration as f64 * samp_rate).round() as u64;
+
+        // Mark the main lobe of the spectrum of the pulse
+        let half_width = 1.0 / pulse.duration.max(1e-6) as f64;
+        let freq = pulse.freq as f64;
+
+        let mut comment = format!("signal_strength={}", pulse.signal_strength);
+        if let Some(snr) = pulse.snr {
+            comment += &format!(", snr={}", snr);
+        }
+        if let Some(PulseFlag::Leakage { source, level }) = pulse.flag {
+            comment += &format!(", leakage from target {} ({:.1} dB)", source, level);
+        }
+
+        self.annotations.push(Annotation {
+            sample_start,
+            sample_count,
+            freq_lower_edge: Some(freq - half_width),
+            freq_upper_edge: Some(freq + half_width),
+            label: Some(format!("target {}", pulse.target_id)),
+            comment: Some(comment),
+        });
+    }
+
+    /// The format of the samples in the recording
+    pub fn sample_format(&self) -> io::Result<SampleFormat> {
+        datatype_format(&self.global.datatype).ok_or_else(|| {
+            let msg = format!("Unsupported SigMF datatype: {}", self.global.datatype);
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        })
+    }
+
+    /// Updates the sample rate, center frequency and sample format of `sdr_config` to match the
+    /// recording.
+    pub fn apply(&self, sdr_config: &mut SdrConfig) -> io::Result<()> {
+        sdr_config.sample_format = self.sample_format()?;
+        sdr_config.samp_rate = self.global.sample_rate as u64;
+        if let Some(freq) = self.captures.first().and_then(|x| x.frequency) {
+            sdr_config.center_freq = freq as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a SigMF recording, consisting of the raw samples from an SDR and annotations marking
+/// any pulses detected in them. The metadata is written when the recording is finished.
+pub struct Writer {
+    path: PathBuf,
+    data: BufWriter<File>,
+    metadata: Metadata,
+}
+
+impl Writer {
+    /// Create a new recording at `path` with samples in the format given by
+    /// `SdrConfig::sample_format`.
+    pub fn create(path: &Path, sdr_config: &SdrConfig, start_time: Timestamp) -> io::Result<Writer> {
+        Ok(Writer {
+            path: path.to_owned(),
+            data: BufWriter::new(File::create(data_path(path))?),
+            metadata: Metadata::new(sdr_config, start_time),
+        })
+    }
+
+    /// Append raw samples to the recording
+    pub fn write_samples(&mut self, samples: &[u8]) -> io::Result<()> {
+        self.data.write_all(samples)
+    }
+
+    /// Add an annotation for a pulse detected in the recording
+    pub fn add_pulse(&mut self, pulse: &Pulse) {
+        self.metadata.add_pulse(pulse);
+    }
+
+    /// Flush the samples and write the metadata of the recording
+    pub fn finish(mut self) -> io::Result<()> {
+        self.data.flush()?;
+        self.metadata.write(&self.path)
+    }
+}
+
+/// Checks whether `path` refers to part of a SigMF recording
+pub fn is_sigmf(path: &Path) -> bool {
+    let extension = path.extension().and_then(|x| x.to_str());
+    extension == Some(META_EXTENSION) || extension == Some(DATA_EXTENSION)
+}
+
+/// The path of the metadata file of the recording containing `path`
+pub fn meta_path(path: &Path) -> PathBuf {
+    path.with_extension(META_EXTENSION)
+}
+
+/// The path of the data file of the recording containing `path`
+pub fn data_path(path: &Path) -> PathBuf {
+    path.with_extension(DATA_EXTENSION)
+}
+
+/// Converts a SigMF datatype into a sample format
+pub fn datatype_format(datatype: &str) -> Option<SampleFormat> {
+    match datatype {
+        "cu8" => Some(SampleFormat::Cu8),
+        "ci8" => Some(SampleFormat::Cs8),
+        "ci16_le" => Some(SampleFormat::Cs16),
+        "cf32_le" => Some(SampleFormat::Cf32),
+        _ => None,
+    }
+}
+
+/// Converts a sample format into a SigMF datatype
+pub fn format_datatype(format: SampleFormat) -> &'static str {
+    match format {
+        SampleFormat::Cu8 => "cu8",
+        SampleFormat::Cs8 => "ci8",
+        SampleFormat::Cs16 => "ci16_le",
+        SampleFormat::Cf32 => "cf32_le",
+    }
+}
+
+/// Formats a timestamp as an ISO 8601 UTC date and time, e.g. `2018-04-01T10:20:30.000000Z`
+pub fn format_datetime(timestamp: Timestamp) -> String {
+    let days = (timestamp.seconds / 86400) as i64;
+    let secs = timestamp.seconds % 86400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year, month, day, secs / 3600, secs / 60 % 60, secs % 60, timestamp.nanos / 1000
+    )
+}
+
+/// Parses an ISO 8601 UTC date and time, e.g. `2018-04-01T10:20:30.5Z`
+pub fn parse_datetime(datetime: &str) -> Option<Timestamp> {
+    let datetime = datetime.trim().trim_end_matches('Z');
+    let mut parts = datetime.splitn(2, 'T');
+    let (date, time) = (parts.next()?, parts.next()?);
+
+    let mut date = date.splitn(3, '-').map(|x| x.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    let mut time = time.splitn(3, ':');
+    let hours: u64 = time.next()?.parse().ok()?;
+    let minutes: u64 = time.next()?.parse().ok()?;
+
+    let mut seconds = time.next()?.splitn(2, '.');
+    let whole_seconds: u64 = seconds.next()?.parse().ok()?;
+    let nanos = match seconds.next() {
+        Some(fraction) => {
+            let digits: String = fraction.chars().chain("000000000".chars()).take(9).collect();
+            digits.parse().ok()?
+        }
+        None => 0,
+    };
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+
+    let seconds = days as u64 * 86400 + hours * 3600 + minutes * 60 + whole_seconds;
+    Some(Timestamp { seconds, nanos })
+}
+
+/// Converts a number of days since 1970-01-01 into a (year, month, day) date.
+/// (http://howardhinnant.github.io/date_algorithms.html)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date into the number of days since 1970-01-01
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_metadata() {
+        let meta = r#"{
+            "global": {
+                "core:datatype": "ci16_le",
+                "core:sample_rate": 2000000,
+                "core:version": "0.0.2",
+                "core:description": "A test recording"
+            },
+            "captures": [{ "core:sample_start": 0, "core:frequency": 150100000 }],
+            "annotations": []
+        }"#;
+
+        let metadata: Metadata = serde_json::from_str(meta).unwrap();
+        let mut sdr_config = SdrConfig::default();
+        metadata.apply(&mut sdr_config).unwrap();
+
+        assert_eq!(sdr_config.sample_format, SampleFormat::Cs16);
+        assert_eq!(sdr_config.samp_rate, 2_000_000);
+        assert_eq!(sdr_config.center_freq, 150_100_000);
+        assert_eq!(metadata.global.extra["core:description"], "A test recording");
+
+        assert!(is_sigmf(Path::new("capture.sigmf-data")));
+        assert!(!is_sigmf(Path::new("capture.bin")));
+        assert_eq!(meta_path(Path::new("a/capture.sigmf-data")), Path::new("a/capture.sigmf-meta"));
+    }
+
+    #[test]
+    fn datetime() {
+        let timestamp = Timestamp { seconds: 1_522_578_030, nanos: 250_000_000 };
+        assert_eq!(format_datetime(timestamp), "2018-04-01T10:20:30.250000Z");
+        assert_eq!(parse_datetime("2018-04-01T10:20:30.25Z"), Some(timestamp));
+        assert_eq!(parse_datetime("2018-04-01T10:20:30Z").unwrap().nanos, 0);
+        assert_eq!(parse_datetime("2018-04-01"), None);
+    }
+
+    #[test]
+    fn annotate_pulses() {
+        let sdr_config = SdrConfig {
+            samp_rate: 2_000_000,
+            center_freq: 150_000_000,
+            sample_format: SampleFormat::Cu8,
+            ..SdrConfig::default()
+        };
+        let start_time = Timestamp { seconds: 1_000, nanos: 0 };
+        let mut metadata = Metadata::new(&sdr_config, start_time);
+
+        metadata.add_pulse(&Pulse {
+            target_id: 1,
+            freq: 150_100_000.0,
+            duration: 0.02,
+            signal_strength: 0.5,
+            noise_floor: None,
+            snr: None,
+            period: None,
+            gain: 0.0,
+            timestamp: start_time.add_nanos(500_000_000),
+            flag: None,
+            slot: None,
+            shape: None,
+        });
+
+        // The metadata can be read back in
+        let metadata: Metadata = serde_json::from_str(&serde_json::to_string(&metadata).unwrap()).unwrap();
+        assert_eq!(metadata.global.datatype, "cu8");
+        assert_eq!(metadata.start_time(), Some(start_time));
+
+        let annotation = &metadata.annotations[0];
+        assert_eq!(annotation.sample_start, 1_000_000);
+        assert_eq!(annotation.sample_count, 40_000);
+        assert!((annotation.freq_lower_edge.unwrap() - 150_099_950.0).abs() < 1e-3);
+        assert_eq!(annotation.label.as_ref().unwrap(), "target 1");
+    }
+}
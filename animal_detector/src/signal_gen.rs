@@ -0,0 +1,446 @@
+//! A generator for synthetic VHF tag signals, for testing the detectors against a known set of
+//! pulses.
+
+use std::f32;
+use std::f64::consts::PI;
+use std::u64;
+
+use common::{DetectionAlgorithm, PulseTarget};
+
+/// The duration (in seconds) of the pulses of tags made by `TagConfig::new`
+const TAG_DURATION: f32 = 0.02;
+
+/// The configuration of a single simulated tag
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TagConfig {
+    /// The frequency (in Hz) of the tag relative to the center frequency of the signal
+    pub freq: f32,
+
+    /// The duration (in seconds) of each pulse
+    pub duration: f32,
+
+    /// The time (in seconds) between the start of consecutive pulses
+    pub period: f32,
+
+    /// The amplitude of the pulses
+    pub amplitude: f32,
+
+    /// The time (in seconds) of the start of the first pulse
+    #[serde(default)]
+    pub first_pulse: f32,
+
+    /// The rate (in Hz/s) that the frequency of the tag's oscillator drifts at
+    #[serde(default)]
+    pub drift: f32,
+
+    /// The phase noise of the tag's oscillator, modelled as a random walk with a standard
+    /// deviation of `phase_noise` radians after one second
+    #[serde(default)]
+    pub phase_noise: f32,
+}
+
+impl TagConfig {
+    /// A tag with 20 ms pulses and an ideal oscillator
+    pub fn new(freq: f32, period: f32, amplitude: f32, first_pulse: f32) -> TagConfig {
+        TagConfig { freq, duration: TAG_DURATION, period, amplitude, first_pulse, drift: 0.0, phase_noise: 0.0 }
+    }
+}
+
+/// Short bursts of broadband noise, e.g. from ignition systems or electric fences
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InterferenceConfig {
+    /// The average number of bursts per second
+    pub rate: f32,
+
+    /// The duration (in seconds) of each burst
+    pub duration: f32,
+
+    /// The RMS amplitude of each burst
+    pub amplitude: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GeneratorConfig {
+    pub samp_rate: u64,
+
+    pub tags: Vec<TagConfig>,
+
+    /// The RMS amplitude of the complex white Gaussian noise added to the signal
+    #[serde(default)]
+    pub noise: f32,
+
+    #[serde(default)]
+    pub interference: Option<InterferenceConfig>,
+
+    /// The seed for the random number generator, signals generated with the same seed are
+    /// identical
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl GeneratorConfig {
+    /// A signal with `tags` in white noise (with an RMS amplitude of `noise`) without interference
+    pub fn new(samp_rate: u64, tags: Vec<TagConfig>, noise: f32, seed: u64) -> GeneratorConfig {
+        GeneratorConfig { samp_rate, tags, noise, interference: None, seed }
+    }
+}
+
+/// Adds a tone (in float32, I/Q format) with an amplitude of 1 that is on for the samples in
+/// `[start, end)`
+pub fn add_pulse(samples: &mut [f32], samp_rate: f32, freq: f32, start: usize, end: usize) {
+    for n in start..end {
+        let phase = 2.0 * f32::consts::PI * freq * n as f32 / samp_rate;
+        samples[2 * n] += phase.cos();
+        samples[2 * n + 1] += phase.sin();
+    }
+}
+
+/// A target that detects the pulses of tags made by `TagConfig::new` at `freq` (in Hz, including
+/// the center frequency of the signal), when the pulses are well above the noise
+pub fn tag_target(freq: f32) -> PulseTarget {
+    PulseTarget {
+        id: None,
+        name: None,
+        freq,
+        duration: TAG_DURATION,
+        duration_variance: 0.004,
+        duration_range: None,
+        threshold: 0.1,
+        cfar: None,
+        edge_length: 3,
+        peak_lookahead: 2,
+        gain: 0.0,
+        period: None,
+        mortality_period: None,
+        period_variance: 0.0,
+        filter_by_period: false,
+        afc: None,
+        algorithm: DetectionAlgorithm::Edge,
+        shape_limits: None,
+    }
+}
+
+/// A pulse included in the generated signal
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TruePulse {
+    /// The index of the tag in `GeneratorConfig::tags`
+    pub tag: usize,
+
+    /// The index of the first sample of the pulse
+    pub start: u64,
+
+    /// The number of samples in the pulse
+    pub length: u64,
+
+    /// The frequency (in Hz) of the tag relative to the center frequency at the start of the pulse
+    pub freq: f32,
+
+    pub amplitude: f32,
+}
+
+/// The state of a single tag
+struct Tag {
+    config: TagConfig,
+
+    /// The sample index of the start of the next (or current) pulse
+    pulse_start: u64,
+
+    /// The number of samples in each pulse
+    pulse_length: u64,
+
+    /// The accumulated phase noise (in radians), and the sample index it was last updated at
+    phase_noise: f64,
+    phase_noise_index: u64,
+}
+
+/// Generates complex baseband samples (in float32, I/Q format) containing pulses from a set of
+/// tags, together with the list of the pulses that were generated.
+pub struct Generator {
+    samp_rate: f64,
+    tags: Vec<Tag>,
+    noise: f32,
+    interference: Option<InterferenceConfig>,
+    rng: Rng,
+
+    /// The index of the next sample to generate
+    sample_index: u64,
+
+    /// The start and end sample of the next (or current) interference burst
+    burst: (u64, u64),
+}
+
+impl Generator {
+    pub fn new(config: GeneratorConfig) -> Generator {
+        let samp_rate = config.samp_rate as f64;
+        let tags = config.tags.into_iter()
+            .map(|config| Tag {
+                pulse_start: (config.first_pulse as f64 * samp_rate).round() as u64,
+                pulse_length: (config.duration as f64 * samp_rate).round() as u64,
+                phase_noise: 0.0,
+                phase_noise_index: 0,
+                config,
+            })
+            .collect();
+
+        let mut generator = Generator {
+            samp_rate,
+            tags,
+            noise: config.noise,
+            interference: config.interference,
+            rng: Rng::new(config.seed),
+            sample_index: 0,
+            burst: (u64::MAX, u64::MAX),
+        };
+        generator.next_burst();
+        generator
+    }
+
+    /// The index of the next sample that will be generated
+    pub fn sample_index(&self) -> u64 {
+        self.sample_index
+    }
+
+    /// Generate the next `num_samples` samples, returning the samples and the pulses that start
+    /// within them.
+    pub fn generate(&mut self, num_samples: usize) -> (Vec<f32>, Vec<TruePulse>) {
+        let mut samples = vec![0.0; num_samples * 2];
+        let pulses = self.fill(&mut samples);
+        (samples, pulses)
+    }
+
+    /// Fill `buffer` with the next samples, returning the pulses that start within them.
+    pub fn fill(&mut self, buffer: &mut [f32]) -> Vec<TruePulse> {
+        let start = self.sample_index;
+        let end = start + (buffer.len() / 2) as u64;
+
+        // Background noise
+        let noise = self.noise / 2.0_f32.sqrt();
+        for value in buffer.iter_mut() {
+            *value = noise * self.rng.gaussian();
+        }
+
+        let mut pulses = vec![];
+        for (id, tag) in self.tags.iter_mut().enumerate() {
+            tag.add_pulses(id, self.samp_rate, start, buffer, &mut self.rng, &mut pulses);
+        }
+        pulses.sort_by_key(|x| x.start);
+
+        self.add_interference(start, buffer);
+
+        self.sample_index = end;
+        pulses
+    }
+
+    fn add_interference(&mut self, start: u64, buffer: &mut [f32]) {
+        let end = start + (buffer.len() / 2) as u64;
+        let amplitude = match self.interference {
+            Some(ref config) => config.amplitude / 2.0_f32.sqrt(),
+            None => return,
+        };
+
+        while self.burst.0 < end {
+            let first = self.burst.0.max(start);
+            let last = self.burst.1.min(end);
+            for n in first..last {
+                let i = (n - start) as usize;
+                buffer[2 * i] += amplitude * self.rng.gaussian();
+                buffer[2 * i + 1] += amplitude * self.rng.gaussian();
+            }
+
+            if self.burst.1 > end {
+                break;
+            }
+            self.next_burst();
+        }
+    }
+
+    /// Schedule the next interference burst after the current one
+    fn next_burst(&mut self) {
+        let config = match self.interference {
+            Some(ref config) if config.rate > 0.0 => config,
+            _ => return,
+        };
+
+        let gap = -self.rng.uniform().max(1e-12).ln() / config.rate as f64 * self.samp_rate;
+        let start = if self.burst.1 == u64::MAX { 0 } else { self.burst.1 } + gap as u64;
+        self.burst = (start, start + (config.duration as f64 * self.samp_rate).round() as u64);
+    }
+}
+
+impl Tag {
+    /// Add the pulses that overlap the samples `[start, start + buffer.len() / 2)` to `buffer`,
+    /// recording any pulses that start in the buffer in `pulses`.
+    fn add_pulses(
+        &mut self,
+        id: usize,
+        samp_rate: f64,
+        start: u64,
+        buffer: &mut [f32],
+        rng: &mut Rng,
+        pulses: &mut Vec<TruePulse>,
+    ) {
+        let end = start + (buffer.len() / 2) as u64;
+        let period = ((self.config.period as f64 * samp_rate).round() as u64).max(1);
+
+        // The standard deviation of the phase noise added at each sample
+        let phase_noise = self.config.phase_noise as f64 / samp_rate.sqrt();
+        let freq = self.config.freq as f64;
+        let drift = self.config.drift as f64;
+        let amplitude = self.config.amplitude;
+
+        while self.pulse_start < end {
+            let pulse_end = self.pulse_start + self.pulse_length;
+
+            if self.pulse_start >= start {
+                let t = self.pulse_start as f64 / samp_rate;
+                pulses.push(TruePulse {
+                    tag: id,
+                    start: self.pulse_start,
+                    length: self.pulse_length,
+                    freq: (freq + drift * t) as f32,
+                    amplitude,
+                });
+            }
+
+            let first = self.pulse_start.max(start);
+            let last = pulse_end.min(end);
+
+            // The phase noise is a random walk, so the change over the time between pulses can be
+            // added in a single step.
+            if first > self.phase_noise_index {
+                let gap = (first - self.phase_noise_index) as f64;
+                self.phase_noise += phase_noise * gap.sqrt() * rng.gaussian() as f64;
+            }
+
+            for n in first..last {
+                self.phase_noise += phase_noise * rng.gaussian() as f64;
+
+                let t = n as f64 / samp_rate;
+                let cycles = freq * t + 0.5 * drift * t * t;
+                let phase = 2.0 * PI * cycles.fract() + self.phase_noise;
+
+                let i = (n - start) as usize;
+                buffer[2 * i] += amplitude * phase.cos() as f32;
+                buffer[2 * i + 1] += amplitude * phase.sin() as f32;
+            }
+            self.phase_noise_index = self.phase_noise_index.max(last);
+
+            if pulse_end > end {
+                break;
+            }
+            self.pulse_start += period;
+        }
+    }
+}
+
+/// Mixed into the seed so that small seeds give well distributed initial states
+const SEED_MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// A small deterministic random number generator (xorshift64*), so generated signals can be
+/// reproduced exactly from their seed.
+struct Rng {
+    state: u64,
+
+    /// The second value generated by the last Box-Muller transform
+    spare: Option<f32>,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // The state must never be zero, otherwise the generator only produces zeros
+        let state = match seed ^ SEED_MIX {
+            0 => SEED_MIX,
+            state => state,
+        };
+        Rng { state, spare: None }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A uniformly distributed value in `[0, 1)`
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// A normally distributed value with zero mean and unit variance
+    fn gaussian(&mut self) -> f32 {
+        if let Some(value) = self.spare.take() {
+            return value;
+        }
+
+        let radius = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+        let angle = 2.0 * PI * self.uniform();
+        self.spare = Some((radius * angle.sin()) as f32);
+        (radius * angle.cos()) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ground_truth() {
+        let tags = vec![TagConfig::new(10_000.0, 0.5, 1.0, 0.1), TagConfig::new(-20_000.0, 0.3, 1.0, 0.0)];
+        let config = GeneratorConfig::new(100_000, tags, 0.0, 1);
+        let (samples, pulses) = Generator::new(config.clone()).generate(100_000);
+
+        // Generating the signal in pieces produces the same result as a single buffer
+        let mut generator = Generator::new(config);
+        let mut pieces = vec![];
+        let mut piece_pulses = vec![];
+        for _ in 0..10 {
+            let (samples, pulses) = generator.generate(10_000);
+            pieces.extend(samples);
+            piece_pulses.extend(pulses);
+        }
+        assert_eq!(samples, pieces);
+        assert_eq!(pulses, piece_pulses);
+
+        let starts: Vec<_> = pulses.iter().map(|x| (x.tag, x.start)).collect();
+        assert_eq!(starts, [(1, 0), (0, 10_000), (1, 30_000), (0, 60_000), (1, 60_000), (1, 90_000)]);
+        assert!(pulses.iter().all(|x| x.length == 2_000));
+
+        // The signal is only present during the pulses
+        let power = |n: usize| samples[2 * n].powi(2) + samples[2 * n + 1].powi(2);
+        assert!((power(10_500) - 1.0).abs() < 1e-4);
+        assert_eq!(power(20_000), 0.0);
+    }
+
+    #[test]
+    fn noise_and_interference() {
+        let config = GeneratorConfig {
+            interference: Some(InterferenceConfig { rate: 10.0, duration: 0.001, amplitude: 10.0 }),
+            ..GeneratorConfig::new(100_000, vec![], 0.5, 2)
+        };
+        let (samples, _) = Generator::new(config.clone()).generate(1_000_000);
+        let (same, _) = Generator::new(config).generate(1_000_000);
+        assert_eq!(samples, same);
+
+        // Count the starts of bursts of 1 ms blocks with strong interference, expecting roughly 100
+        let loud: Vec<bool> = samples.chunks(200)
+            .map(|x| x.iter().map(|x| x * x).sum::<f32>() / 100.0 > 10.0)
+            .collect();
+        let bursts = loud.windows(2).filter(|x| !x[0] && x[1]).count();
+        assert!(bursts >= 70 && bursts < 130, "{}", bursts);
+
+        // Outside of the bursts, the noise has the expected power
+        let quiet: Vec<f32> = samples.chunks(200)
+            .filter(|x| x.iter().map(|x| x * x).sum::<f32>() / 100.0 < 1.0)
+            .flat_map(|x| x.iter().cloned())
+            .collect();
+        let power = quiet.iter().map(|x| x * x).sum::<f32>() / (quiet.len() / 2) as f32;
+        assert!((power - 0.25).abs() < 0.01, "{}", power);
+    }
+
+    #[test]
+    fn rng_state_never_zero() {
+        let mut rng = Rng::new(SEED_MIX);
+        assert!((0..10).map(|_| rng.next_u64()).any(|x| x != 0));
+    }
+}
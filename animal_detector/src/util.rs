@@ -1,3 +1,7 @@
+//! Conversions between raw samples, sample counts and times.
+
+use common::Timestamp;
+
 // Code used to generate lookup table
 // fn gen_lookup_table() -> [f32; 256] {
 //     let mut data = [0.0; 256];
@@ -44,4 +48,20 @@ const LOOKUP_TABLE: [f32; 256] = [
 #[inline]
 pub fn convert_iq(i: u8, q: u8) -> (f32, f32) {
     (LOOKUP_TABLE[i as usize], LOOKUP_TABLE[q as usize])
-}
\ No newline at end of file
+}
+
+/// Converts a number of samples at `samp_rate` into nanoseconds
+pub fn samples_to_nanos(samples: u64, samp_rate: u64) -> u64 {
+    (samples as u128 * 1_000_000_000 / samp_rate.max(1) as u128) as u64
+}
+
+/// Converts a number of nanoseconds into a number of samples at `samp_rate`
+pub fn nanos_to_samples(nanos: u64, samp_rate: u64) -> u64 {
+    (nanos as u128 * samp_rate as u128 / 1_000_000_000) as u64
+}
+
+/// The number of nanoseconds from `start` to `end` (saturating at zero)
+pub fn nanos_between(start: Timestamp, end: Timestamp) -> u64 {
+    let to_nanos = |x: Timestamp| x.seconds as u128 * 1_000_000_000 + x.nanos as u128;
+    to_nanos(end).saturating_sub(to_nanos(start)) as u64
+}
@@ -2,16 +2,22 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 
+mod scan;
 mod sdr;
 mod signal;
 
-pub use sdr::SdrConfig;
-pub use signal::{Pulse, PulseTarget, Timestamp};
+pub use scan::{ScanCandidate, ScanConfig, ScanResult};
+pub use sdr::{HoppingConfig, LeakageConfig, SampleFormat, SdrConfig};
+pub use signal::{
+    AfcConfig, CfarConfig, DetectionAlgorithm, DurationRange, DurationStats, Pulse, PulseFlag, PulseShape,
+    PulseTarget, RejectedTarget, RejectionReason, ShapeLimits, TagStatus, TargetStatus, Timestamp,
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum UpMessage {
     PulseTargets(Vec<PulseTarget>),
     SdrConfig(SdrConfig),
+    Scan(ScanConfig),
     Start,
     Stop
 }
@@ -19,4 +25,8 @@ pub enum UpMessage {
 #[derive(Clone, Serialize, Deserialize)]
 pub enum DownMessage {
     Pulse(Pulse),
+    TargetStatus(TargetStatus),
+    ScanResult(ScanResult),
+    RejectedTarget(RejectedTarget),
+    DurationStats(DurationStats),
 }
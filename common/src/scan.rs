@@ -0,0 +1,49 @@
+use signal::Timestamp;
+
+/// Configuration for scanning the entire bandwidth of the SDR for pulses from unknown targets
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ScanConfig {
+    /// The minimum duration (in seconds) of a pulse
+    pub min_duration: f32,
+
+    /// The maximum duration (in seconds) of a pulse
+    pub max_duration: f32,
+
+    /// The multiple of the noise floor of each frequency bin that an edge must exceed to be
+    /// detected
+    pub multiplier: f32,
+
+    /// The number of samples used for edge detection
+    pub edge_length: i64,
+
+    /// The number of samples for rejecting false positives in peak detection
+    pub peak_lookahead: i64,
+
+    /// The length of time (in seconds) between each report of candidate frequencies
+    pub report_interval: f32,
+}
+
+/// A frequency where pulses were detected while scanning
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ScanCandidate {
+    /// The center frequency (in Hz) of the frequency bin containing the pulses
+    pub freq: f32,
+
+    /// The number of pulses detected since the last report
+    pub pulse_count: usize,
+
+    /// The mean duration (in seconds) of the detected pulses
+    pub duration: f32,
+
+    /// The maximum signal strength of the detected pulses
+    pub signal_strength: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanResult {
+    /// The frequencies where pulses were detected, ordered by frequency
+    pub candidates: Vec<ScanCandidate>,
+
+    /// The time at the end of the report interval (from UNIX epoch)
+    pub timestamp: Timestamp,
+}
@@ -1,3 +1,6 @@
+use std::fmt;
+use std::str::FromStr;
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct SdrConfig {
     pub samp_rate: u64,
@@ -8,4 +11,119 @@ pub struct SdrConfig {
     pub amp_enable: bool,
     pub antenna_enable: bool,
     pub baseband_filter: Option<u32>,
+
+    /// The format of the raw I/Q samples produced by the SDR
+    #[serde(default)]
+    pub sample_format: SampleFormat,
+
+    /// If set, the SDR is retuned between several center frequencies when the targets do not fit
+    /// within its bandwidth, and `center_freq` is ignored
+    #[serde(default)]
+    pub hopping: Option<HoppingConfig>,
+
+    /// If set, pulses detected at the same time as a stronger pulse from a target with a nearby
+    /// frequency are checked for leakage
+    #[serde(default)]
+    pub leakage_check: Option<LeakageConfig>,
+}
+
+/// Configuration for hopping between center frequencies
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HoppingConfig {
+    /// The fraction of the sample rate around each center frequency that targets are placed in,
+    /// which avoids the roll off of the SDR's filters at the edges of the band
+    pub usable_bandwidth: f32,
+
+    /// The time (in seconds) that samples are discarded for after retuning, while the SDR settles
+    pub settle_time: f32,
+
+    /// The time (in seconds) spent at a center frequency for targets without a known period
+    pub default_dwell: f32,
+}
+
+impl Default for HoppingConfig {
+    fn default() -> HoppingConfig {
+        HoppingConfig { usable_bandwidth: 0.8, settle_time: 0.05, default_dwell: 2.0 }
+    }
+}
+
+/// Configuration for checking whether pulses are leakage of a stronger pulse from another target
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LeakageConfig {
+    /// Pulses less than this many dB above the strength expected to leak from a simultaneous pulse
+    /// of another target are considered leakage
+    pub margin: f32,
+
+    /// Whether leakage is dropped, rather than output with a `PulseFlag::Leakage` flag
+    #[serde(default)]
+    pub suppress: bool,
+}
+
+impl Default for LeakageConfig {
+    fn default() -> LeakageConfig {
+        LeakageConfig { margin: 6.0, suppress: false }
+    }
+}
+
+/// The format of interleaved I/Q samples
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit integers, offset by 128 (e.g. RTL-SDR)
+    #[serde(rename = "cu8")]
+    Cu8,
+
+    /// Signed 8-bit integers (e.g. HackRF)
+    #[serde(rename = "cs8")]
+    Cs8,
+
+    /// Signed 16-bit little endian integers
+    #[serde(rename = "cs16")]
+    Cs16,
+
+    /// 32-bit little endian floats (e.g. Airspy)
+    #[serde(rename = "cf32")]
+    Cf32,
+}
+
+impl Default for SampleFormat {
+    fn default() -> SampleFormat {
+        SampleFormat::Cs8
+    }
+}
+
+impl SampleFormat {
+    /// The number of bytes in each I/Q sample
+    pub fn sample_size(self) -> usize {
+        match self {
+            SampleFormat::Cu8 | SampleFormat::Cs8 => 2,
+            SampleFormat::Cs16 => 4,
+            SampleFormat::Cf32 => 8,
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            SampleFormat::Cu8 => "cu8",
+            SampleFormat::Cs8 => "cs8",
+            SampleFormat::Cs16 => "cs16",
+            SampleFormat::Cf32 => "cf32",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SampleFormat, String> {
+        match s {
+            "cu8" => Ok(SampleFormat::Cu8),
+            "cs8" => Ok(SampleFormat::Cs8),
+            "cs16" => Ok(SampleFormat::Cs16),
+            "cf32" => Ok(SampleFormat::Cf32),
+            _ => Err(format!("Unknown sample format: {}", s)),
+        }
+    }
 }
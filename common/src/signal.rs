@@ -1,7 +1,16 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PulseTarget {
+    /// The ID reported with pulses from this target. If unset, the index of the target in the list
+    /// of targets is used.
+    #[serde(default)]
+    pub id: Option<usize>,
+
+    /// A human readable name for the target (e.g. the animal the tag is attached to)
+    #[serde(default)]
+    pub name: Option<String>,
+
     /// The frequency (in Hz) of the pulse that we are trying to detect
     pub freq: f32,
 
@@ -11,9 +20,17 @@ pub struct PulseTarget {
     /// The maximum variance (in seconds) allowed in the pulse pulse duration
     pub duration_variance: f32,
 
-    /// The threshold used for filtering noise
+    /// If set, pulses with any duration in the range are detected, and `duration` is ignored
+    #[serde(default)]
+    pub duration_range: Option<DurationRange>,
+
+    /// The threshold used for filtering noise, ignored if `cfar` is set
     pub threshold: f32,
 
+    /// If set, the threshold is computed from an estimate of the noise floor instead
+    #[serde(default)]
+    pub cfar: Option<CfarConfig>,
+
     /// The number of samples used for edge detection
     pub edge_length: i64,
 
@@ -22,9 +39,149 @@ pub struct PulseTarget {
 
     /// Any additional gain associated with this target
     pub gain: f32,
+
+    /// The expected time (in seconds) between consecutive pulses, if known
+    #[serde(default)]
+    pub period: Option<f32>,
+
+    /// The expected time (in seconds) between consecutive pulses when the tag is in mortality
+    /// mode, if known
+    #[serde(default)]
+    pub mortality_period: Option<f32>,
+
+    /// The maximum variance (in seconds) allowed in the pulse period
+    #[serde(default)]
+    pub period_variance: f32,
+
+    /// Whether pulses that are inconsistent with the expected period (or mortality period) should
+    /// be dropped
+    #[serde(default)]
+    pub filter_by_period: bool,
+
+    /// If set, the frequency of the target is tracked as it drifts away from `freq`
+    #[serde(default)]
+    pub afc: Option<AfcConfig>,
+
+    /// The algorithm used to detect pulses
+    #[serde(default)]
+    pub algorithm: DetectionAlgorithm,
+
+    /// If set, pulses whose envelope does not look like a keyed CW tag pulse are dropped
+    #[serde(default)]
+    pub shape_limits: Option<ShapeLimits>,
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+impl PulseTarget {
+    /// The ID of the target, given its index in the list of targets
+    pub fn id_or(&self, index: usize) -> usize {
+        self.id.unwrap_or(index)
+    }
+}
+
+/// The algorithm used to find pulses in the signal strength of a target
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetectionAlgorithm {
+    /// Pairs the rising and falling edges of the pulse
+    #[serde(rename = "edge")]
+    Edge,
+
+    /// Correlates the signal strength with the expected pulse envelope, which is more sensitive
+    /// for weak pulses
+    #[serde(rename = "matched_filter")]
+    MatchedFilter,
+}
+
+impl Default for DetectionAlgorithm {
+    fn default() -> DetectionAlgorithm {
+        DetectionAlgorithm::Edge
+    }
+}
+
+/// Configuration for constant false alarm rate (CFAR) thresholding
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CfarConfig {
+    /// The length of time (in seconds) used to estimate the noise floor
+    pub window: f32,
+
+    /// The multiple of the noise floor to use as the threshold for edge detection
+    pub multiplier: f32,
+}
+
+/// Configuration for automatic frequency control (AFC)
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AfcConfig {
+    /// The maximum distance (in Hz) that the tracked frequency can drift from the configured
+    /// frequency
+    pub max_offset: f32,
+
+    /// The fraction of the measured frequency error that is corrected after each pulse
+    pub rate: f32,
+}
+
+/// A range of pulse durations, for targets where the pulse width is not known exactly
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DurationRange {
+    /// The shortest pulse duration (in seconds)
+    pub min: f32,
+
+    /// The longest pulse duration (in seconds)
+    pub max: f32,
+
+    /// If set, once this many pulses have been detected the range is replaced by the duration and
+    /// variance suggested by the measured durations (see `DurationStats`)
+    #[serde(default)]
+    pub tighten_after: Option<usize>,
+}
+
+/// Limits on the envelope of a pulse (see `PulseShape`). Limits that are not set are not checked.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShapeLimits {
+    /// The maximum rise time (in seconds)
+    #[serde(default)]
+    pub max_rise_time: Option<f32>,
+
+    /// The maximum fall time (in seconds)
+    #[serde(default)]
+    pub max_fall_time: Option<f32>,
+
+    /// The minimum flatness of the top of the pulse
+    #[serde(default)]
+    pub min_flatness: Option<f32>,
+
+    /// The minimum ratio (in dB) between the energy of the pulse and the energy either side of it
+    #[serde(default)]
+    pub min_on_off_ratio: Option<f32>,
+}
+
+impl ShapeLimits {
+    /// Whether `shape` is within all of the limits
+    pub fn accepts(&self, shape: &PulseShape) -> bool {
+        self.max_rise_time.map_or(true, |x| shape.rise_time <= x)
+            && self.max_fall_time.map_or(true, |x| shape.fall_time <= x)
+            && self.min_flatness.map_or(true, |x| shape.flatness >= x)
+            && self.min_on_off_ratio.map_or(true, |x| shape.on_off_ratio >= x)
+    }
+}
+
+/// Features of the magnitude envelope of a pulse. A keyed CW tag pulse has short edges, a flat top
+/// and little energy either side of the pulse.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PulseShape {
+    /// The time (in seconds) for the envelope to rise from 10% to 90% of the pulse level
+    pub rise_time: f32,
+
+    /// The time (in seconds) for the envelope to fall from 90% to 10% of the pulse level
+    pub fall_time: f32,
+
+    /// One minus the coefficient of variation of the middle of the pulse, where 1 is perfectly
+    /// flat
+    pub flatness: f32,
+
+    /// The ratio (in dB) between the mean energy of the pulse and the mean energy either side of it
+    pub on_off_ratio: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Timestamp {
     pub seconds: u64,
     pub nanos: u32,
@@ -41,6 +198,24 @@ impl Timestamp {
     pub fn millis(&self) -> u64 {
         self.seconds * 1000 + self.nanos as u64 / 1_000_000
     }
+
+    /// Returns a timestamp `nanos` nanoseconds after this timestamp.
+    pub fn add_nanos(&self, nanos: u64) -> Timestamp {
+        Timestamp::from_total_nanos(self.total_nanos() + nanos as u128)
+    }
+
+    /// Returns a timestamp `nanos` nanoseconds before this timestamp (saturating at zero).
+    pub fn sub_nanos(&self, nanos: u64) -> Timestamp {
+        Timestamp::from_total_nanos(self.total_nanos().saturating_sub(nanos as u128))
+    }
+
+    fn total_nanos(&self) -> u128 {
+        self.seconds as u128 * 1_000_000_000 + self.nanos as u128
+    }
+
+    fn from_total_nanos(total: u128) -> Timestamp {
+        Timestamp { seconds: (total / 1_000_000_000) as u64, nanos: (total % 1_000_000_000) as u32 }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
@@ -48,7 +223,7 @@ pub struct Pulse {
     /// The ID associated with this pulse
     pub target_id: usize,
 
-    /// The frequency of the pulse
+    /// The frequency of the pulse (in Hz), estimated from the signal if AFC is enabled
     pub freq: f32,
 
     /// The duration of the pulse (in seconds)
@@ -57,9 +232,148 @@ pub struct Pulse {
     /// The strength of the pulse
     pub signal_strength: f32,
 
+    /// The estimated noise floor of the target's frequency bin when the pulse was detected, in
+    /// the same units as `signal_strength`
+    #[serde(default)]
+    pub noise_floor: Option<f32>,
+
+    /// The signal to noise ratio (in dBs) of the pulse, from the mean signal strength over the
+    /// duration of the pulse and the noise floor
+    #[serde(default)]
+    pub snr: Option<f32>,
+
+    /// The measured time (in seconds) between this pulse and the previous pulse from the same
+    /// target. If the target has an expected period, this accounts for any missed pulses.
+    #[serde(default)]
+    pub period: Option<f32>,
+
     /// The gain (in dBs to use with the pulse)
     pub gain: f32,
 
-    /// The time when the pulse was recorded (from UNIX epoch)
+    /// The time of the rising edge of the pulse (from UNIX epoch), derived from the sample count
+    /// of the SDR stream.
     pub timestamp: Timestamp,
+
+    /// Set if the pulse may be spurious, with the reason why
+    #[serde(default)]
+    pub flag: Option<PulseFlag>,
+
+    /// The index of the center frequency the SDR was tuned to when the pulse was detected, if the
+    /// SDR is hopping between center frequencies
+    #[serde(default)]
+    pub slot: Option<usize>,
+
+    /// Features of the envelope of the pulse, if enough of the signal around the pulse was kept to
+    /// measure them
+    #[serde(default)]
+    pub shape: Option<PulseShape>,
+}
+
+/// The reason a pulse has been flagged as possibly spurious
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PulseFlag {
+    /// The pulse may be leakage of a stronger pulse detected at the same time by a target with a
+    /// nearby frequency
+    Leakage {
+        /// The ID of the target that detected the stronger pulse
+        source: usize,
+
+        /// The signal strength expected to leak from the stronger pulse, relative to the strength
+        /// of this pulse (in dBs)
+        level: f32,
+    },
+}
+
+/// The behavior of a tag, classified from its observed pulse rate
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TagStatus {
+    /// The tag is pulsing at its normal rate
+    Normal,
+
+    /// The tag is pulsing at its mortality rate (the animal has not moved for an extended period)
+    Mortality,
+
+    /// There are not enough recent pulses to classify the tag
+    Unknown,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct TargetStatus {
+    /// The ID of the target
+    pub target_id: usize,
+
+    /// The current status of the target
+    pub status: TagStatus,
+
+    /// The median pulse period (in seconds) used to classify the target
+    pub period: Option<f32>,
+
+    /// The time when the status changed (from UNIX epoch)
+    pub timestamp: Timestamp,
+}
+
+/// The distribution of the measured durations of the pulses from a target with a `DurationRange`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DurationStats {
+    /// The ID of the target
+    pub target_id: usize,
+
+    /// The number of pulses measured
+    pub count: usize,
+
+    /// The median duration (in seconds) of the recent pulses
+    pub median: f32,
+
+    /// The shortest and longest durations (in seconds) of the recent pulses
+    pub min: f32,
+    pub max: f32,
+
+    /// The number of pulses with each duration, where the first bin starts at the minimum of the
+    /// range and each bin is `bin_width` seconds wide
+    pub histogram: Vec<usize>,
+    pub bin_width: f32,
+
+    /// The duration and duration variance (in seconds) that would detect the recent pulses
+    pub suggested_duration: f32,
+    pub suggested_variance: f32,
+
+    /// Whether the target has been narrowed to the suggested duration
+    pub tightened: bool,
+
+    /// The new configuration of the target, if it has been narrowed to the suggested duration
+    #[serde(default)]
+    pub target: Option<PulseTarget>,
+
+    /// Why the target could not be narrowed to the suggested duration, if changing it failed
+    #[serde(default)]
+    pub error: Option<String>,
+
+    /// The time of the report (from UNIX epoch)
+    pub timestamp: Timestamp,
+}
+
+/// A target that pulses are not being detected for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RejectedTarget {
+    /// The ID of the target
+    pub target_id: usize,
+
+    /// The name of the target, if it has one
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Why the target was rejected
+    pub reason: RejectionReason,
+}
+
+/// The reason a target has been rejected
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    /// The frequency of the target is outside the bandwidth of the SDR
+    OutOfBand,
+
+    /// Another target has the same ID
+    DuplicateId,
 }
@@ -8,16 +8,23 @@ use std::{
 };
 
 use airspy::{self, AirspyContext, Airspy};
-use animal_detector::Detectors;
 use serde_json;
 
-use task::{Task, TaskData, TaskContext};
-use types::{InnerMessage, SdrConfig, PulseTarget};
+use task::{Task, TaskData, TaskContext, Processor};
+use types::{InnerMessage, SdrConfig, PulseTarget, SampleFormat, ScanConfig};
+
+/// The format of the samples produced by the Airspy
+const SAMPLE_FORMAT: SampleFormat = SampleFormat::Cf32;
 
 #[derive(Deserialize)]
 struct TaskConfig {
     sdr_config: SdrConfig,
     pulse_targets: Vec<PulseTarget>,
+
+    /// If set, the full bandwidth is scanned for unknown targets instead of detecting pulses from
+    /// `pulse_targets`
+    #[serde(default)]
+    scan: Option<ScanConfig>,
 }
 
 pub struct AirspyTask {
@@ -27,10 +34,11 @@ pub struct AirspyTask {
 
 impl AirspyTask {
     pub fn new() -> Result<AirspyTask, Box<Error>> {
-        let config = {
+        let mut config: TaskConfig = {
             let file = File::open("config/airspy.json")?;
             serde_json::from_reader(BufReader::new(file)).unwrap()
         };
+        config.sdr_config.sample_format = SAMPLE_FORMAT;
 
         Ok(AirspyTask { context: airspy::init()?, config })
     }
@@ -49,11 +57,24 @@ impl AirspyTask {
 
         let mut data = AirspyTaskData {
             device: &airspy,
-            detectors: Detectors::new(&self.config.sdr_config, &self.config.pulse_targets),
+            processor: Processor::new(
+                &self.config.sdr_config,
+                &self.config.pulse_targets,
+                self.config.scan.as_ref()
+            ),
             stream: stream,
         };
 
-        let msg = data.process(context)?;
+        // Target updates are applied without restarting the SDR, any other message stops it
+        let msg = loop {
+            match data.process(context)? {
+                Some(InnerMessage::PulseTargets(targets)) => {
+                    data.processor.update_targets(&self.config.sdr_config, &targets);
+                    self.pulse_targets(*targets)?;
+                }
+                msg => break msg,
+            }
+        };
 
         data.stream.stop()?;
         println!("Stopped Airspy");
@@ -65,11 +86,17 @@ impl AirspyTask {
 impl Task for AirspyTask {
     fn pulse_targets(&mut self, targets: Vec<PulseTarget>) -> Result<(), Box<Error>> {
         self.config.pulse_targets = targets;
+        self.config.scan = None;
         Ok(())
     }
 
     fn sdr_config(&mut self, config: SdrConfig) -> Result<(), Box<Error>> {
-        self.config.sdr_config = config;
+        self.config.sdr_config = SdrConfig { sample_format: SAMPLE_FORMAT, ..config };
+        Ok(())
+    }
+
+    fn scan(&mut self, config: ScanConfig) -> Result<(), Box<Error>> {
+        self.config.scan = Some(config);
         Ok(())
     }
 
@@ -88,7 +115,7 @@ impl Task for AirspyTask {
 
 struct AirspyTaskData<'a> {
     device: &'a Airspy,
-    detectors: Detectors,
+    processor: Processor,
     stream: airspy::RxStream<'a>
 }
 
@@ -103,9 +130,9 @@ impl<'a> TaskData for AirspyTaskData<'a> {
                 Err(e) => return Err(e.into()),
             };
 
-            let pulses = self.detectors.next_f32(&data);
-            for pulse in pulses {
-                tx.send(InnerMessage::Pulse(Box::new(pulse)))?;
+            self.processor.next_f32(&data, tx)?;
+            if let Some(freq) = self.processor.retune() {
+                self.device.set_freq(freq as u32)?;
             }
         }
     }
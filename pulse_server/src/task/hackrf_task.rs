@@ -8,16 +8,23 @@ use std::{
 };
 
 use hackrf::{self, HackRFContext, HackRF};
-use animal_detector::Detectors;
 use serde_json;
 
-use task::{Task, TaskData, TaskContext};
-use types::{InnerMessage, SdrConfig, PulseTarget};
+use task::{Task, TaskData, TaskContext, Processor};
+use types::{InnerMessage, SdrConfig, PulseTarget, SampleFormat, ScanConfig};
+
+/// The format of the samples produced by the HackRF
+const SAMPLE_FORMAT: SampleFormat = SampleFormat::Cs8;
 
 #[derive(Deserialize)]
 struct TaskConfig {
     sdr_config: SdrConfig,
     pulse_targets: Vec<PulseTarget>,
+
+    /// If set, the full bandwidth is scanned for unknown targets instead of detecting pulses from
+    /// `pulse_targets`
+    #[serde(default)]
+    scan: Option<ScanConfig>,
 }
 
 pub struct HackRfTask {
@@ -27,10 +34,11 @@ pub struct HackRfTask {
 
 impl HackRfTask {
     pub fn new() -> Result<HackRfTask, Box<Error>> {
-        let config = {
+        let mut config: TaskConfig = {
             let file = File::open("config/hackrf.json")?;
             serde_json::from_reader(BufReader::new(file)).unwrap()
         };
+        config.sdr_config.sample_format = SAMPLE_FORMAT;
 
         Ok(HackRfTask { context: hackrf::init()?, config })
     }
@@ -55,11 +63,24 @@ impl HackRfTask {
 
         let mut data = HackRfTaskData {
             device: &device,
-            detectors: Detectors::new(&self.config.sdr_config, &self.config.pulse_targets),
+            processor: Processor::new(
+                &self.config.sdr_config,
+                &self.config.pulse_targets,
+                self.config.scan.as_ref()
+            ),
             stream: stream,
         };
 
-        let msg = data.process(context)?;
+        // Target updates are applied without restarting the SDR, any other message stops it
+        let msg = loop {
+            match data.process(context)? {
+                Some(InnerMessage::PulseTargets(targets)) => {
+                    data.processor.update_targets(&self.config.sdr_config, &targets);
+                    self.pulse_targets(*targets)?;
+                }
+                msg => break msg,
+            }
+        };
 
         data.stream.stop()?;
         println!("Stopped HackRF");
@@ -71,11 +92,17 @@ impl HackRfTask {
 impl Task for HackRfTask {
     fn pulse_targets(&mut self, targets: Vec<PulseTarget>) -> Result<(), Box<Error>> {
         self.config.pulse_targets = targets;
+        self.config.scan = None;
         Ok(())
     }
 
     fn sdr_config(&mut self, config: SdrConfig) -> Result<(), Box<Error>> {
-        self.config.sdr_config = config;
+        self.config.sdr_config = SdrConfig { sample_format: SAMPLE_FORMAT, ..config };
+        Ok(())
+    }
+
+    fn scan(&mut self, config: ScanConfig) -> Result<(), Box<Error>> {
+        self.config.scan = Some(config);
         Ok(())
     }
 
@@ -94,7 +121,7 @@ impl Task for HackRfTask {
 
 struct HackRfTaskData<'a> {
     device: &'a HackRF,
-    detectors: Detectors,
+    processor: Processor,
     stream: hackrf::RxStream<'a>
 }
 
@@ -109,9 +136,9 @@ impl<'a> TaskData for HackRfTaskData<'a> {
                 Err(e) => return Err(e.into()),
             };
 
-            let pulses = self.detectors.next(&data);
-            for pulse in pulses {
-                tx.send(InnerMessage::Pulse(Box::new(pulse)))?;
+            self.processor.next(&data, tx)?;
+            if let Some(freq) = self.processor.retune() {
+                self.device.set_freq(freq)?;
             }
         }
     }
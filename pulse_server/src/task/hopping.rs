@@ -0,0 +1,322 @@
+use std::{cmp::Ordering, error::Error, mem, sync::mpsc::Sender};
+
+use animal_detector::{util, Detectors};
+use common::{HoppingConfig, RejectionReason, RejectedTarget, Timestamp};
+
+use task::send_detector_events;
+use types::{InnerMessage, Pulse, PulseTarget, SdrConfig};
+
+/// A center frequency that the SDR is tuned to, along with the targets detected there
+struct Slot {
+    center_freq: u64,
+    detectors: Detectors,
+
+    /// The number of samples to process before moving to the next slot
+    dwell: u64,
+}
+
+/// Detects pulses from targets spread over more than the bandwidth of the SDR, by retuning the SDR
+/// between a set of center frequencies in a round-robin.
+pub struct Hopper {
+    sdr_config: SdrConfig,
+    config: HoppingConfig,
+    slots: Vec<Slot>,
+
+    /// The index of the slot the SDR is currently tuned to
+    current: usize,
+
+    /// The number of samples processed (or discarded) since the SDR was last retuned
+    sample_count: u64,
+
+    /// The time that the first sample was received, which is shared by the detectors of all slots
+    start_time: Option<Timestamp>,
+
+    /// The number of samples received since the start time, at any slot. The SDR keeps streaming
+    /// while it is retuned and settles, so this counts the time spent at every slot.
+    sample_index: u64,
+
+    /// The center frequency that the SDR needs to be retuned to, if any
+    retune: Option<u64>,
+
+    /// Targets rejected by the last target update, that have not yet been reported
+    rejected_targets: Vec<RejectedTarget>,
+}
+
+impl Hopper {
+    pub fn new(sdr_config: &SdrConfig, config: HoppingConfig, targets: &[PulseTarget]) -> Hopper {
+        let mut hopper = Hopper {
+            sdr_config: sdr_config.clone(),
+            config,
+            slots: vec![],
+            current: 0,
+            sample_count: 0,
+            start_time: None,
+            sample_index: 0,
+            retune: None,
+            rejected_targets: vec![],
+        };
+        hopper.update_targets(targets);
+        hopper
+    }
+
+    /// Regroup the targets into slots. Slots with the same center frequency as before keep their
+    /// detectors, so unchanged targets in those slots keep their state.
+    pub fn update_targets(&mut self, targets: &[PulseTarget]) {
+        // Targets are split between several detectors, so they are given explicit IDs first to
+        // keep the IDs of targets without one the same as their index in the full list.
+        let mut unique: Vec<PulseTarget> = vec![];
+        for (index, target) in targets.iter().enumerate() {
+            let id = target.id_or(index);
+            if unique.iter().any(|x| x.id == Some(id)) {
+                self.rejected_targets.push(RejectedTarget {
+                    target_id: id,
+                    name: target.name.clone(),
+                    reason: RejectionReason::DuplicateId,
+                });
+                continue;
+            }
+            unique.push(PulseTarget { id: Some(id), ..target.clone() });
+        }
+
+        let bandwidth = self.config.usable_bandwidth * self.sdr_config.samp_rate as f32;
+        let mut old_slots = mem::replace(&mut self.slots, vec![]);
+        for group in group_targets(unique, bandwidth) {
+            let center_freq = center_freq(&group);
+            let dwell = (self.dwell_time(&group) * self.sdr_config.samp_rate as f32) as u64;
+
+            let detectors = match old_slots.iter().position(|x| x.center_freq == center_freq) {
+                Some(index) => {
+                    let mut detectors = old_slots.swap_remove(index).detectors;
+                    detectors.update_targets(&group);
+                    detectors
+                }
+                None => {
+                    let sdr_config = SdrConfig { center_freq, ..self.sdr_config.clone() };
+                    let mut detectors = Detectors::new(&sdr_config, &group);
+                    if let Some(start_time) = self.start_time {
+                        detectors.set_start_time(start_time);
+                    }
+                    detectors
+                }
+            };
+            self.slots.push(Slot { center_freq, detectors, dwell });
+        }
+
+        println!("Hopping between {} center frequencies:", self.slots.len());
+        for slot in &self.slots {
+            println!("  {} Hz, dwell {} samples", slot.center_freq, slot.dwell);
+        }
+
+        // Restart the round-robin from the first slot
+        self.current = 0;
+        self.sample_count = 0;
+        self.retune = self.slots.first().map(|x| x.center_freq);
+    }
+
+    /// The time (in seconds) to stay at a center frequency with the targets in `group`, which is
+    /// long enough for at least one pulse from every target with a known period.
+    fn dwell_time(&self, group: &[PulseTarget]) -> f32 {
+        let settle_time = self.config.settle_time;
+        group.iter()
+            .map(|target| {
+                let period = match (target.period, target.mortality_period) {
+                    (Some(period), Some(mortality_period)) => period.max(mortality_period),
+                    (Some(period), None) | (None, Some(period)) => period,
+                    (None, None) => return self.config.default_dwell,
+                };
+                // Allow for the pulse to finish, and be detected, at the end of the dwell
+                period + target.period_variance + 2.0 * (target.duration + target.duration_variance)
+            })
+            .fold(0.0, f32::max) + settle_time
+    }
+
+    /// Returns the center frequency that the SDR needs to be retuned to, if the SDR should move to
+    /// another slot.
+    pub fn retune(&mut self) -> Option<u64> {
+        self.retune.take()
+    }
+
+    /// Process a buffer of `num_samples` samples with `process`, tagging each pulse with the slot
+    /// it came from. Samples received while the SDR settles after retuning are discarded.
+    pub fn next<F>(&mut self, num_samples: u64, process: F, tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>>
+        where F: FnOnce(&mut Detectors) -> Vec<Pulse>
+    {
+        for target in self.rejected_targets.drain(..) {
+            println!("Rejected target {}: {:?}", target.target_id, target.reason);
+            tx.send(InnerMessage::RejectedTarget(Box::new(target)))?;
+        }
+
+        if self.slots.is_empty() {
+            return Ok(());
+        }
+
+        let samp_rate = self.sdr_config.samp_rate;
+        if self.start_time.is_none() {
+            // The buffer has just been received, so the first sample was captured one buffer
+            // length ago.
+            let start_time = Timestamp::now().sub_nanos(util::samples_to_nanos(num_samples, samp_rate));
+            for slot in &mut self.slots {
+                slot.detectors.set_start_time(start_time);
+            }
+            self.start_time = Some(start_time);
+        }
+
+        let settle = (self.config.settle_time * samp_rate as f32) as u64;
+        let slot = &mut self.slots[self.current];
+
+        if self.sample_count >= settle {
+            if self.sample_count < settle + num_samples {
+                // This is the first buffer since the SDR settled, so skip over the time spent at
+                // other slots
+                slot.detectors.resume_at(self.sample_index);
+            }
+
+            for mut pulse in process(&mut slot.detectors) {
+                pulse.slot = Some(self.current);
+                tx.send(InnerMessage::Pulse(Box::new(pulse)))?;
+            }
+        }
+        for slot in &mut self.slots {
+            send_detector_events(&mut slot.detectors, tx)?;
+        }
+
+        self.sample_count += num_samples;
+        self.sample_index += num_samples;
+        if self.sample_count >= self.slots[self.current].dwell && self.slots.len() > 1 {
+            self.current = (self.current + 1) % self.slots.len();
+            self.sample_count = 0;
+            self.retune = Some(self.slots[self.current].center_freq);
+        }
+
+        Ok(())
+    }
+}
+
+/// Group `targets` into the fewest sets of targets whose frequencies span at most `bandwidth` Hz.
+fn group_targets(mut targets: Vec<PulseTarget>, bandwidth: f32) -> Vec<Vec<PulseTarget>> {
+    targets.sort_by(|a, b| a.freq.partial_cmp(&b.freq).unwrap_or(Ordering::Equal));
+
+    // Starting each group at the lowest remaining frequency is optimal for covering points on a
+    // line with intervals of a fixed width.
+    let mut groups: Vec<Vec<PulseTarget>> = vec![];
+    for target in targets {
+        match groups.last_mut() {
+            Some(group) if target.freq - group[0].freq <= bandwidth => group.push(target),
+            _ => groups.push(vec![target]),
+        }
+    }
+    groups
+}
+
+/// The center frequency in the middle of the targets in `group`
+fn center_freq(group: &[PulseTarget]) -> u64 {
+    let min = group.first().map_or(0.0, |x| x.freq as f64);
+    let max = group.last().map_or(0.0, |x| x.freq as f64);
+    ((min + max) / 2.0).round() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use std::slice;
+    use std::sync::mpsc::{self, Receiver};
+
+    use animal_detector::signal_gen::tag_target;
+    use common::PulseFlag;
+
+    use super::*;
+
+    const SAMP_RATE: u64 = 1_024_000;
+
+    fn test_pulse(target_id: usize) -> Pulse {
+        Pulse {
+            target_id,
+            freq: 0.0,
+            duration: 0.02,
+            signal_strength: 1.0,
+            noise_floor: None,
+            snr: None,
+            period: None,
+            gain: 0.0,
+            timestamp: Timestamp { seconds: 0, nanos: 0 },
+            flag: None::<PulseFlag>,
+            slot: None,
+            shape: None,
+        }
+    }
+
+    fn test_hopper(targets: &[PulseTarget]) -> Hopper {
+        let sdr_config = SdrConfig { samp_rate: SAMP_RATE, ..SdrConfig::default() };
+        let config = HoppingConfig { usable_bandwidth: 0.8, settle_time: 0.05, default_dwell: 0.2 };
+        Hopper::new(&sdr_config, config, targets)
+    }
+
+    /// The slots of the pulses received from `rx`
+    fn pulse_slots(rx: &Receiver<InnerMessage>) -> Vec<Option<usize>> {
+        rx.try_iter()
+            .filter_map(|msg| match msg {
+                InnerMessage::Pulse(pulse) => Some(pulse.slot),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_targets_within_bandwidth() {
+        let freqs = [150_300_000.0, 150_000_000.0, 150_100_000.0, 150_500_000.0, 151_000_000.0];
+        let targets: Vec<_> = freqs.iter().map(|&freq| tag_target(freq)).collect();
+
+        let groups: Vec<Vec<f32>> = group_targets(targets, 200_000.0).iter()
+            .map(|group| group.iter().map(|x| x.freq).collect())
+            .collect();
+        assert_eq!(groups, vec![
+            vec![150_000_000.0, 150_100_000.0],
+            vec![150_300_000.0, 150_500_000.0],
+            vec![151_000_000.0],
+        ]);
+        assert_eq!(center_freq(&[tag_target(150_300_000.0), tag_target(150_500_000.0)]), 150_400_000);
+    }
+
+    #[test]
+    fn dwell_covers_one_period() {
+        let hopper = test_hopper(&[]);
+        let periodic = PulseTarget { period: Some(1.0), period_variance: 0.01, ..tag_target(150_000_000.0) };
+        let mortality = PulseTarget { mortality_period: Some(1.5), ..periodic.clone() };
+
+        // The period, its variance and two pulse lengths, plus the settle time
+        let expected = 1.0 + 0.01 + 2.0 * 0.024 + 0.05;
+        assert!((hopper.dwell_time(slice::from_ref(&periodic)) - expected).abs() < 1e-4);
+        assert!((hopper.dwell_time(&[periodic.clone(), mortality]) - (expected + 0.5)).abs() < 1e-4);
+
+        // Targets without a period use the default dwell time
+        let unknown = tag_target(150_000_000.0);
+        assert!((hopper.dwell_time(slice::from_ref(&unknown)) - 0.25).abs() < 1e-4);
+        assert!((hopper.dwell_time(&[periodic, unknown]) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn discards_settle_samples_and_tags_slots() {
+        let mut hopper = test_hopper(&[tag_target(150_000_000.0), tag_target(151_000_000.0)]);
+        let (mut tx, rx) = mpsc::channel();
+        assert_eq!(hopper.retune(), Some(150_000_000));
+
+        // The settle time is 51_200 samples, and each slot has a dwell of 256_000 samples
+        let buffer = 40_000;
+        let mut processed = vec![];
+        for _ in 0..8 {
+            let mut called = false;
+            hopper.next(buffer, |_| { called = true; vec![test_pulse(0)] }, &mut tx).unwrap();
+            processed.push(called);
+        }
+        assert_eq!(processed, vec![false, false, true, true, true, true, true, false]);
+        assert_eq!(pulse_slots(&rx), vec![Some(0); 5]);
+
+        // The 7th buffer ends the dwell at the first slot, and the 8th is discarded while the SDR
+        // settles at the second slot
+        assert_eq!(hopper.retune(), Some(151_000_000));
+        for _ in 0..3 {
+            hopper.next(buffer, |_| vec![test_pulse(1)], &mut tx).unwrap();
+        }
+        assert_eq!(pulse_slots(&rx), vec![Some(1); 2]);
+        assert_eq!(hopper.retune(), None);
+    }
+}
@@ -1,10 +1,15 @@
 pub mod test_task;
 pub mod airspy_task;
 pub mod hackrf_task;
+pub mod hopping;
 
 use std::{thread, error::Error, sync::mpsc::{Receiver, Sender, TryRecvError}, time::Duration};
 
-use types::{InnerMessage, PulseTarget, SdrConfig};
+use animal_detector::{Detectors, scanner::Scanner};
+
+use self::hopping::Hopper;
+
+use types::{InnerMessage, Pulse, PulseTarget, ScanConfig, ScanResult, SdrConfig};
 
 pub trait TaskData {
     fn tick(&mut self, tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>>;
@@ -33,6 +38,10 @@ pub trait Task: Sized {
     fn sdr_config(&mut self, _config: SdrConfig) -> Result<(), Box<Error>> {
         Ok(())
     }
+
+    fn scan(&mut self, _config: ScanConfig) -> Result<(), Box<Error>> {
+        Ok(())
+    }
 }
 
 pub struct TaskContext {
@@ -45,6 +54,7 @@ pub fn run_task<T: Task>(task: &mut T, mut context: TaskContext) -> Result<(), B
         match msg {
             InnerMessage::PulseTargets(t) => task.pulse_targets(*t)?,
             InnerMessage::SdrConfig(config) => task.sdr_config(*config)?,
+            InnerMessage::Scan(config) => task.scan(*config)?,
             InnerMessage::Start => return task.start(context),
             _ => {}
         };
@@ -67,3 +77,103 @@ pub fn run_task<T: Task>(task: &mut T, mut context: TaskContext) -> Result<(), B
         };
     }
 }
+
+/// The processing applied to the samples received from an SDR
+pub enum Processor {
+    /// Detect pulses from the configured pulse targets
+    Detect(Detectors),
+
+    /// Scan the full bandwidth of the SDR for pulses from unknown targets
+    Scan(Scanner),
+
+    /// Detect pulses from the configured pulse targets, retuning the SDR between several center
+    /// frequencies
+    Hop(Hopper),
+}
+
+impl Processor {
+    pub fn new(sdr_config: &SdrConfig, targets: &[PulseTarget], scan: Option<&ScanConfig>) -> Processor {
+        match (scan, sdr_config.hopping) {
+            (Some(scan), _) => Processor::Scan(Scanner::new(sdr_config, scan)),
+            (None, Some(hopping)) => Processor::Hop(Hopper::new(sdr_config, hopping, targets)),
+            (None, None) => Processor::Detect(Detectors::new(sdr_config, targets)),
+        }
+    }
+
+    /// Update the targets while the SDR is running. Detectors for unchanged targets keep their
+    /// state, and a scan is stopped to detect the targets instead. Any targets that are rejected
+    /// are reported with the next set of results.
+    pub fn update_targets(&mut self, sdr_config: &SdrConfig, targets: &[PulseTarget]) {
+        match *self {
+            Processor::Detect(ref mut detectors) => detectors.update_targets(targets),
+            Processor::Scan(_) => *self = Processor::new(sdr_config, targets, None),
+            Processor::Hop(ref mut hopper) => hopper.update_targets(targets),
+        }
+    }
+
+    /// Returns the center frequency that the SDR needs to be retuned to, if any. This should be
+    /// checked after each set of samples is processed.
+    pub fn retune(&mut self) -> Option<u64> {
+        match *self {
+            Processor::Hop(ref mut hopper) => hopper.retune(),
+            _ => None,
+        }
+    }
+
+    /// Process the next set of samples (in u8, I/Q format), sending the results to `tx`
+    pub fn next(&mut self, samples: &[u8], tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>> {
+        match *self {
+            Processor::Detect(ref mut detectors) => {
+                let pulses = detectors.next(samples);
+                send_pulses(detectors, pulses, tx)
+            }
+            Processor::Scan(ref mut scanner) => send_scan_results(scanner.next(samples), tx),
+            Processor::Hop(ref mut hopper) => {
+                hopper.next((samples.len() / 2) as u64, |detectors| detectors.next(samples), tx)
+            }
+        }
+    }
+
+    /// Process the next set of samples (in float32, I/Q format), sending the results to `tx`
+    pub fn next_f32(&mut self, samples: &[f32], tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>> {
+        match *self {
+            Processor::Detect(ref mut detectors) => {
+                let pulses = detectors.next_f32(samples);
+                send_pulses(detectors, pulses, tx)
+            }
+            Processor::Scan(ref mut scanner) => send_scan_results(scanner.next_f32(samples), tx),
+            Processor::Hop(ref mut hopper) => {
+                hopper.next((samples.len() / 2) as u64, |detectors| detectors.next_f32(samples), tx)
+            }
+        }
+    }
+}
+
+fn send_pulses(detectors: &mut Detectors, pulses: Vec<Pulse>, tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>> {
+    for pulse in pulses {
+        tx.send(InnerMessage::Pulse(Box::new(pulse)))?;
+    }
+    send_detector_events(detectors, tx)
+}
+
+/// Send any status changes, duration reports and rejected targets from `detectors`
+fn send_detector_events(detectors: &mut Detectors, tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>> {
+    for status in detectors.status_changes() {
+        tx.send(InnerMessage::TargetStatus(Box::new(status)))?;
+    }
+    for report in detectors.duration_reports() {
+        tx.send(InnerMessage::DurationStats(Box::new(report)))?;
+    }
+    for target in detectors.rejected_targets() {
+        println!("Rejected target {}: {:?}", target.target_id, target.reason);
+        tx.send(InnerMessage::RejectedTarget(Box::new(target)))?;
+    }
+    Ok(())
+}
+
+fn send_scan_results(results: Vec<ScanResult>, tx: &mut Sender<InnerMessage>) -> Result<(), Box<Error>> {
+    for result in results {
+        tx.send(InnerMessage::ScanResult(Box::new(result)))?;
+    }
+    Ok(())
+}
@@ -67,12 +67,18 @@ impl<'a> TaskData for TestTaskData<'a> {
             for (i, target) in self.task_config.pulse_targets.iter().enumerate() {
                 self.prev_pulse = Instant::now();
                 let pulse = Pulse {
-                    target_id: i,
+                    target_id: target.id_or(i),
                     freq: target.freq,
                     duration: target.duration,
                     signal_strength: 1.0,
+                    noise_floor: None,
+                    snr: None,
+                    period: Some(self.config.rate_ms as f32 / 1000.0),
                     gain: target.gain,
                     timestamp: Timestamp::now(),
+                    flag: None,
+                    slot: None,
+                    shape: None,
                 };
                 tx.send(InnerMessage::Pulse(Box::new(pulse)))?;
             }
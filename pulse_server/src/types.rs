@@ -1,12 +1,19 @@
 use std::fmt;
 use std::net::TcpStream;
 
-pub use common::{UpMessage, DownMessage, PulseTarget, Pulse, SdrConfig, Timestamp};
+pub use common::{UpMessage, DownMessage, PulseTarget, Pulse, RejectedTarget, SdrConfig, TargetStatus, Timestamp};
+pub use common::DurationStats;
+pub use common::{SampleFormat, ScanConfig, ScanResult};
 
 pub enum InnerMessage {
     PulseTargets(Box<Vec<PulseTarget>>),
     SdrConfig(Box<SdrConfig>),
+    Scan(Box<ScanConfig>),
     Pulse(Box<Pulse>),
+    TargetStatus(Box<TargetStatus>),
+    ScanResult(Box<ScanResult>),
+    RejectedTarget(Box<RejectedTarget>),
+    DurationStats(Box<DurationStats>),
     NewConnection(TcpStream),
     Start,
     Stop
@@ -17,7 +24,12 @@ impl fmt::Debug for InnerMessage {
         match self {
             &InnerMessage::PulseTargets(..) => write!(f, "PulseTargets(..)"),
             &InnerMessage::SdrConfig(..) => write!(f, "SdrConfig(..)"),
+            &InnerMessage::Scan(..) => write!(f, "Scan(..)"),
             &InnerMessage::Pulse(ref p) => write!(f, "Pulse({:?})", p),
+            &InnerMessage::TargetStatus(ref s) => write!(f, "TargetStatus({:?})", s),
+            &InnerMessage::ScanResult(ref r) => write!(f, "ScanResult({:?})", r),
+            &InnerMessage::RejectedTarget(ref r) => write!(f, "RejectedTarget({:?})", r),
+            &InnerMessage::DurationStats(ref s) => write!(f, "DurationStats({:?})", s),
             &InnerMessage::NewConnection(..) => write!(f, "NewConnection(..Some)"),
             &InnerMessage::Start => write!(f, "Start"),
             &InnerMessage::Stop => write!(f, "Stop"),
@@ -29,6 +41,10 @@ impl InnerMessage {
     pub fn down(self) -> Option<DownMessage> {
         match self {
             InnerMessage::Pulse(p) => Some(DownMessage::Pulse(*p)),
+            InnerMessage::TargetStatus(s) => Some(DownMessage::TargetStatus(*s)),
+            InnerMessage::ScanResult(r) => Some(DownMessage::ScanResult(*r)),
+            InnerMessage::RejectedTarget(r) => Some(DownMessage::RejectedTarget(*r)),
+            InnerMessage::DurationStats(s) => Some(DownMessage::DurationStats(*s)),
             _ => None,
         }
     }
@@ -37,6 +53,7 @@ impl InnerMessage {
         match msg {
             UpMessage::PulseTargets(x) => InnerMessage::PulseTargets(Box::new(x)),
             UpMessage::SdrConfig(x) => InnerMessage::SdrConfig(Box::new(x)),
+            UpMessage::Scan(x) => InnerMessage::Scan(Box::new(x)),
             UpMessage::Start => InnerMessage::Start,
             UpMessage::Stop => InnerMessage::Stop,
         }
@@ -3,13 +3,19 @@ use std::process::Command;
 use regex::Regex;
 use rocket_contrib::json::Json;
 
-use connection::{drone, globals};
+use connection::{bearing, drone, globals};
 use types::{
     ServerMessage,
     PulseServerMessage,
     Coordinate,
     Location,
     PulseWithTelemetry,
+    BearingSweep,
+    DurationStats,
+    RejectedTarget,
+    ScanResult,
+    TargetStatus,
+    SweepConfig,
     Telemetry,
     NavWaypoint,
     GenericMsg,
@@ -70,6 +76,16 @@ pub fn set_yaw(value: Json<f32>) {
     drone::set_yaw(value.0, 0.0, 1.0);
 }
 
+#[post("/drone/bearing-sweep", data = "<config>")]
+pub fn start_bearing_sweep(config: Json<SweepConfig>) -> Option<String> {
+    bearing::start_sweep(config.0).err()
+}
+
+#[get("/targets/bearing")]
+pub fn get_bearing_sweep() -> Json<BearingSweep> {
+    Json(globals::get_bearing_sweep())
+}
+
 #[post("/drone/set-logging", data = "<value>")]
 pub fn set_logging(value: Json<Option<String>>) -> Option<String> {
     match value.0 {
@@ -117,3 +133,23 @@ pub fn get_pulses(index: usize) -> Json<Vec<PulseWithTelemetry>> {
 pub fn get_latest_pulses() -> Json<Vec<PulseWithTelemetry>> {
     Json(globals::get_latest_pulses())
 }
+
+#[get("/targets/status")]
+pub fn get_target_status() -> Json<Vec<TargetStatus>> {
+    Json(globals::get_target_status())
+}
+
+#[get("/targets/durations")]
+pub fn get_duration_stats() -> Json<Vec<DurationStats>> {
+    Json(globals::get_duration_stats())
+}
+
+#[get("/targets/rejected")]
+pub fn get_rejected_targets() -> Json<Vec<RejectedTarget>> {
+    Json(globals::get_rejected_targets())
+}
+
+#[get("/scan")]
+pub fn get_scan_result() -> Json<Option<ScanResult>> {
+    Json(globals::get_scan_result())
+}
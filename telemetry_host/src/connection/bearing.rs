@@ -0,0 +1,288 @@
+//! Estimates the bearing of each target by rotating the drone on the spot and fitting the gain
+//! pattern of the directional antenna to the signal strength of the pulses received at each
+//! heading.
+
+use std::{
+    thread,
+    cmp::Ordering,
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use connection::{drone, globals};
+use types::{AntennaPattern, BearingEstimate, PulseWithTelemetry, SweepConfig};
+
+/// The minimum number of pulses needed to estimate the bearing of a target
+const MIN_SAMPLES: usize = 4;
+
+/// The sweep is split into steps, as the yaw command takes the shortest path to an absolute heading
+const SWEEP_STEPS: usize = 4;
+
+/// How close (in degrees) the heading must be to the target heading for a step to be complete
+const HEADING_TOLERANCE: f32 = 5.0;
+
+/// The number of sectors used to measure how much of the rotation pulses were received over
+const COVERAGE_SECTORS: usize = 12;
+
+/// Start a bearing sweep at the current position of the drone
+pub fn start_sweep(config: SweepConfig) -> Result<(), String> {
+    if config.turn_rate <= 0.0 {
+        return Err("The turn rate must be positive".into());
+    }
+
+    if !globals::start_bearing_sweep(drone::get_telemetry().location) {
+        return Err("A bearing sweep is already running".into());
+    }
+
+    thread::spawn(move || run_sweep(config));
+    Ok(())
+}
+
+fn run_sweep(config: SweepConfig) {
+    let start_yaw = drone::get_telemetry().location.yaw;
+    let start_index = globals::pulse_count();
+    println!("Starting bearing sweep from heading {}", start_yaw);
+
+    // Allow the drone to turn the full quarter rotation at the requested rate, with some margin
+    let step_angle = 360.0 / SWEEP_STEPS as f32;
+    let timeout = Duration::from_millis((2000.0 * step_angle / config.turn_rate) as u64 + 5000);
+
+    for step in 1..=SWEEP_STEPS {
+        let target = wrap_angle(start_yaw + step as f32 * step_angle);
+        drone::set_yaw(target, config.turn_rate, 1.0);
+
+        let step_start = Instant::now();
+        while angle_difference(drone::get_telemetry().location.yaw, target).abs() > HEADING_TOLERANCE {
+            if step_start.elapsed() > timeout {
+                println!("Timed out turning to heading {} during bearing sweep", target);
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    let pulses = globals::get_pulses_since(start_index);
+    let estimates = estimate_bearings(&pulses, &config.antenna);
+    println!("Bearing sweep complete: {:?}", estimates);
+    globals::complete_bearing_sweep(estimates);
+}
+
+/// Estimate the bearing of every target with enough pulses
+fn estimate_bearings(pulses: &[PulseWithTelemetry], antenna: &AntennaPattern) -> Vec<BearingEstimate> {
+    let mut targets: BTreeMap<usize, Vec<(f32, f32)>> = BTreeMap::new();
+    for value in pulses {
+        // Pulses that may be leakage from another target, pulses without a valid heading (the
+        // heading is reported as 655.35 degrees when it is unknown), and pulses without a finite
+        // signal strength (which can't be fitted) are ignored
+        let yaw = value.telemetry.location.yaw;
+        if value.pulse.flag.is_some() || yaw > 360.0 || !value.pulse.signal_strength.is_finite() {
+            continue;
+        }
+        let heading = wrap_angle(yaw + antenna.offset);
+        let samples = targets.entry(value.pulse.target_id).or_insert_with(Vec::new);
+        samples.push((heading, value.pulse.signal_strength));
+    }
+
+    targets.iter()
+        .filter_map(|(&target_id, samples)| fit_bearing(target_id, samples, antenna))
+        .collect()
+}
+
+/// Find the bearing where the antenna pattern best fits the signal strength (in dB) received at
+/// each heading. The overall signal level is unknown, so the pattern is compared after removing the
+/// mean difference between the samples and the pattern.
+fn fit_bearing(target_id: usize, samples: &[(f32, f32)], antenna: &AntennaPattern) -> Option<BearingEstimate> {
+    let n = samples.len();
+    if n < MIN_SAMPLES {
+        return None;
+    }
+
+    let errors: Vec<f32> = (0..360)
+        .map(|bearing| {
+            let residuals: Vec<f32> = samples.iter()
+                .map(|&(heading, rssi)| rssi - pattern_gain(antenna, angle_difference(heading, bearing as f32)))
+                .collect();
+            let mean = residuals.iter().sum::<f32>() / n as f32;
+            residuals.iter().map(|x| (x - mean).powi(2)).sum()
+        })
+        .collect();
+
+    let (best, &min_error) = errors.iter()
+        .enumerate()
+        .min_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))?;
+
+    // Bearings whose error is within the noise variance of the best fit are nearly as likely
+    let variance = min_error / (n - 2) as f32;
+    let within = |direction: isize| {
+        (1..180)
+            .find(|&offset| {
+                let index = (best as isize + direction * offset as isize + 360) % 360;
+                errors[index as usize] - min_error > variance
+            })
+            .unwrap_or(180) as f32
+    };
+    let uncertainty = (within(1) + within(-1)) / 2.0;
+
+    let mean_rssi = samples.iter().map(|x| x.1).sum::<f32>() / n as f32;
+    let total = samples.iter().map(|x| (x.1 - mean_rssi).powi(2)).sum::<f32>();
+    let explained = if total > 0.0 { (1.0 - min_error / total).max(0.0) } else { 0.0 };
+
+    let mut sectors = [false; COVERAGE_SECTORS];
+    for &(heading, _) in samples {
+        let sector = (heading / 360.0 * COVERAGE_SECTORS as f32) as usize;
+        sectors[sector.min(COVERAGE_SECTORS - 1)] = true;
+    }
+    let coverage = sectors.iter().filter(|&&x| x).count() as f32 / COVERAGE_SECTORS as f32;
+
+    Some(BearingEstimate {
+        target_id,
+        bearing: best as f32,
+        uncertainty,
+        confidence: explained * coverage,
+        samples: n,
+    })
+}
+
+/// The gain (in dB) of the antenna at `angle` degrees from its main lobe. The main lobe is modelled
+/// as a power of the cosine of half the angle, scaled to be -3 dB at the edge of the beam, and the
+/// rest of the pattern is `front_to_back` dB below the main lobe.
+fn pattern_gain(antenna: &AntennaPattern, angle: f32) -> f32 {
+    let half_beamwidth = (antenna.beamwidth / 2.0).max(1.0).min(179.0);
+    let power = -3.0 / (20.0 * (half_beamwidth / 2.0).to_radians().cos().log10());
+    let response = (angle / 2.0).to_radians().cos().abs().max(1e-6);
+    (20.0 * power * response.log10()).max(-antenna.front_to_back.abs())
+}
+
+/// Wraps an angle (in degrees) to the range [0, 360)
+fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = angle % 360.0;
+    let wrapped = if wrapped < 0.0 { wrapped + 360.0 } else { wrapped };
+
+    // Adding 360 to a tiny negative angle rounds up to 360
+    if wrapped >= 360.0 { 0.0 } else { wrapped }
+}
+
+/// The signed difference (in degrees) from `b` to `a`, in the range [-180, 180)
+fn angle_difference(a: f32, b: f32) -> f32 {
+    wrap_angle(a - b + 180.0) - 180.0
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32;
+
+    use common::PulseFlag;
+    use types::{Location, Pulse, Telemetry, Timestamp};
+
+    use super::*;
+
+    /// The heading reported by the drone when it is unknown
+    const UNKNOWN_HEADING: f32 = 655.35;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    /// The signal strength received at each heading (every 10 degrees) from a target at `bearing`
+    fn sweep_samples(bearing: f32, antenna: &AntennaPattern) -> Vec<(f32, f32)> {
+        (0..36)
+            .map(|i| {
+                let heading = i as f32 * 10.0;
+                (heading, -40.0 + pattern_gain(antenna, angle_difference(heading, bearing)))
+            })
+            .collect()
+    }
+
+    fn test_pulse(target_id: usize, yaw: f32, signal_strength: f32) -> PulseWithTelemetry {
+        PulseWithTelemetry {
+            telemetry: Telemetry {
+                location: Location { x: 0.0, y: 0.0, alt: 0.0, yaw },
+                velocity: [0.0; 3],
+            },
+            pulse: Pulse {
+                target_id,
+                freq: 150_000_000.0,
+                duration: 0.02,
+                signal_strength,
+                noise_floor: None,
+                snr: None,
+                period: None,
+                gain: 0.0,
+                timestamp: Timestamp { seconds: 0, nanos: 0 },
+                flag: None,
+                slot: None,
+                shape: None,
+            },
+        }
+    }
+
+    #[test]
+    fn wrap_angles() {
+        assert_close(wrap_angle(0.0), 0.0);
+        assert_close(wrap_angle(360.0), 0.0);
+        assert_close(wrap_angle(-10.0), 350.0);
+        assert_close(wrap_angle(725.0), 5.0);
+        assert!(wrap_angle(-1e-5) < 360.0);
+    }
+
+    #[test]
+    fn angle_differences() {
+        assert_close(angle_difference(10.0, 350.0), 20.0);
+        assert_close(angle_difference(350.0, 10.0), -20.0);
+        assert_close(angle_difference(0.0, 360.0), 0.0);
+        assert_close(angle_difference(180.0, 0.0), -180.0);
+        assert_close(angle_difference(90.0, 45.0), 45.0);
+    }
+
+    #[test]
+    fn antenna_pattern() {
+        let antenna = AntennaPattern::default();
+        assert_close(pattern_gain(&antenna, 0.0), 0.0);
+        assert_close(pattern_gain(&antenna, 45.0), -3.0);
+        assert_close(pattern_gain(&antenna, -45.0), -3.0);
+        assert_close(pattern_gain(&antenna, 180.0), -15.0);
+        assert_close(pattern_gain(&antenna, -180.0), -15.0);
+    }
+
+    #[test]
+    fn fit_bearing_across_north() {
+        let antenna = AntennaPattern::default();
+        for &bearing in &[0.0, 5.0, 355.0, 180.0] {
+            let estimate = fit_bearing(3, &sweep_samples(bearing, &antenna), &antenna).unwrap();
+            assert_eq!(estimate.target_id, 3);
+            assert_eq!(estimate.samples, 36);
+            assert_close(estimate.bearing, bearing);
+            assert!(estimate.uncertainty <= 2.0, "{:?}", estimate);
+            assert!(estimate.confidence > 0.99, "{:?}", estimate);
+        }
+
+        let samples = sweep_samples(90.0, &antenna);
+        assert!(fit_bearing(3, &samples[..MIN_SAMPLES - 1], &antenna).is_none());
+    }
+
+    #[test]
+    fn skips_unknown_headings_and_invalid_pulses() {
+        let antenna = AntennaPattern { offset: 90.0, ..AntennaPattern::default() };
+
+        // The antenna points 90 degrees to the right of the nose, so the target at 0 degrees is
+        // strongest when the drone is heading 270 degrees
+        let mut pulses: Vec<_> = sweep_samples(270.0, &AntennaPattern::default()).iter()
+            .map(|&(yaw, rssi)| test_pulse(0, yaw, rssi))
+            .collect();
+        pulses.push(test_pulse(0, UNKNOWN_HEADING, 0.0));
+        pulses.push(test_pulse(0, 90.0, f32::NEG_INFINITY));
+        pulses.push(PulseWithTelemetry {
+            pulse: Pulse { flag: Some(PulseFlag::Leakage { source: 1, level: 0.0 }), ..test_pulse(0, 90.0, 0.0).pulse },
+            ..test_pulse(0, 90.0, 0.0)
+        });
+
+        // A target only heard without a valid heading has no estimate
+        pulses.extend((0..MIN_SAMPLES).map(|_| test_pulse(1, UNKNOWN_HEADING, -40.0)));
+
+        let estimates = estimate_bearings(&pulses, &antenna);
+        assert_eq!(estimates.len(), 1, "{:?}", estimates);
+        assert_eq!(estimates[0].target_id, 0);
+        assert_eq!(estimates[0].samples, 36);
+        assert_close(estimates[0].bearing, 0.0);
+    }
+}
@@ -1,10 +1,23 @@
+use std::collections::BTreeMap;
 use std::sync::{Mutex, mpsc::{Sender, Receiver, channel}};
 
-use types::{PulseWithTelemetry, ServerMessage};
+use types::{
+    BearingEstimate, BearingSweep, DurationStats, Location, PulseWithTelemetry, RejectedTarget, ScanResult,
+    ServerMessage, SweepState, TargetStatus,
+};
 
 lazy_static! {
     pub static ref PULSE_DATA: Mutex<Vec<PulseWithTelemetry>> = Mutex::new(vec![]);
     pub static ref SERVER_SENDER: Mutex<Option<Sender<ServerMessage>>> = Mutex::new(None);
+    pub static ref TARGET_STATUS: Mutex<BTreeMap<usize, TargetStatus>> = Mutex::new(BTreeMap::new());
+    pub static ref SCAN_RESULT: Mutex<Option<ScanResult>> = Mutex::new(None);
+    pub static ref REJECTED_TARGETS: Mutex<BTreeMap<usize, RejectedTarget>> = Mutex::new(BTreeMap::new());
+    pub static ref DURATION_STATS: Mutex<BTreeMap<usize, DurationStats>> = Mutex::new(BTreeMap::new());
+    pub static ref BEARING_SWEEP: Mutex<BearingSweep> = Mutex::new(BearingSweep {
+        state: SweepState::Idle,
+        location: None,
+        estimates: vec![],
+    });
 }
 
 pub fn init_server_channel() -> Receiver<ServerMessage> {
@@ -21,6 +34,11 @@ pub fn add_pulse(pulse: PulseWithTelemetry) {
     PULSE_DATA.lock().unwrap().push(pulse);
 }
 
+/// Returns the total number of pulses that have been received
+pub fn pulse_count() -> usize {
+    PULSE_DATA.lock().unwrap().len()
+}
+
 /// Returns the number of pulses that have occured since the specified index
 pub fn get_pulses_since(index: usize) -> Vec<PulseWithTelemetry> {
     let pulse_data = PULSE_DATA.lock().unwrap();
@@ -42,3 +60,65 @@ pub fn get_latest_pulses() -> Vec<PulseWithTelemetry> {
     }
 }
 
+pub fn set_target_status(status: TargetStatus) {
+    TARGET_STATUS.lock().unwrap().insert(status.target_id, status);
+}
+
+/// Returns the latest status of every target that has reported a status
+pub fn get_target_status() -> Vec<TargetStatus> {
+    TARGET_STATUS.lock().unwrap().values().cloned().collect()
+}
+
+pub fn set_duration_stats(stats: DurationStats) {
+    DURATION_STATS.lock().unwrap().insert(stats.target_id, stats);
+}
+
+/// Returns the latest measured pulse durations of every target with a duration range
+pub fn get_duration_stats() -> Vec<DurationStats> {
+    DURATION_STATS.lock().unwrap().values().cloned().collect()
+}
+
+pub fn add_rejected_target(target: RejectedTarget) {
+    REJECTED_TARGETS.lock().unwrap().insert(target.target_id, target);
+}
+
+pub fn clear_rejected_targets() {
+    REJECTED_TARGETS.lock().unwrap().clear();
+}
+
+/// Returns the targets that the pulse server is not detecting pulses for
+pub fn get_rejected_targets() -> Vec<RejectedTarget> {
+    REJECTED_TARGETS.lock().unwrap().values().cloned().collect()
+}
+
+pub fn set_scan_result(result: ScanResult) {
+    *SCAN_RESULT.lock().unwrap() = Some(result);
+}
+
+/// Returns the most recent result from the pulse server's scan mode
+pub fn get_scan_result() -> Option<ScanResult> {
+    SCAN_RESULT.lock().unwrap().clone()
+}
+
+/// Returns the state of the current (or most recent) bearing sweep
+pub fn get_bearing_sweep() -> BearingSweep {
+    BEARING_SWEEP.lock().unwrap().clone()
+}
+
+/// Marks a bearing sweep as running from `location`, clearing the previous estimates. Returns
+/// false if a sweep is already running.
+pub fn start_bearing_sweep(location: Location) -> bool {
+    let mut sweep = BEARING_SWEEP.lock().unwrap();
+    if let SweepState::Running = sweep.state {
+        return false;
+    }
+    *sweep = BearingSweep { state: SweepState::Running, location: Some(location), estimates: vec![] };
+    true
+}
+
+/// Marks the running bearing sweep as complete with the estimated bearings
+pub fn complete_bearing_sweep(estimates: Vec<BearingEstimate>) {
+    let mut sweep = BEARING_SWEEP.lock().unwrap();
+    sweep.state = SweepState::Complete;
+    sweep.estimates = estimates;
+}
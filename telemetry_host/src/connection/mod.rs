@@ -1,3 +1,4 @@
+pub mod bearing;
 pub mod drone;
 pub mod pulse_server;
 pub mod globals;
@@ -52,7 +53,15 @@ impl TrackingServer {
         while let Ok(msg) = self.server_rx.recv() {
             match msg {
                 ServerMessage::Pulse(value) => self.new_pulse(value),
+                ServerMessage::TargetStatus(status) => globals::set_target_status(status),
+                ServerMessage::ScanResult(result) => globals::set_scan_result(result),
+                ServerMessage::RejectedTarget(target) => globals::add_rejected_target(target),
+                ServerMessage::DurationStats(stats) => globals::set_duration_stats(stats),
                 ServerMessage::PulseServer(msg) => {
+                    // The new targets are checked again by the pulse server
+                    if let PulseServerMessage::PulseTargets(_) = msg {
+                        globals::clear_rejected_targets();
+                    }
                     self.pulse_server_tx.send(msg).unwrap();
                 }
             }
@@ -41,14 +41,33 @@ fn pulse_client_loop(addr: &str,
 fn rx_loop(mut conn: TcpStream, to: &mut Sender<ServerMessage>) -> io::Result<()> {
     let mut buffer = vec![];
     loop {
-        let DownMessage::Pulse(mut pulse) = ipc::read_json(&mut conn, &mut buffer)?;
+        match ipc::read_json(&mut conn, &mut buffer)? {
+            DownMessage::Pulse(mut pulse) => {
+                // Convert pulse to dB
+                pulse.signal_strength = 20.0 * pulse.signal_strength.log10();
+                pulse.noise_floor = pulse.noise_floor.map(|x| 20.0 * x.log10());
+                println!("Pulse from client: {:?}", pulse);
 
-        // Convert pulse to dB
-        pulse.signal_strength = 20.0 * pulse.signal_strength.log10();
-        println!("Pulse from client: {:?}", pulse);
-
-        let data = PulseWithTelemetry { telemetry: drone::get_telemetry(), pulse };
-        to.send(ServerMessage::Pulse(data)).unwrap();
+                let data = PulseWithTelemetry { telemetry: drone::get_telemetry(), pulse };
+                to.send(ServerMessage::Pulse(data)).unwrap();
+            }
+            DownMessage::TargetStatus(status) => {
+                println!("Target status changed: {:?}", status);
+                to.send(ServerMessage::TargetStatus(status)).unwrap();
+            }
+            DownMessage::ScanResult(result) => {
+                println!("Scan result: {:?}", result.candidates);
+                to.send(ServerMessage::ScanResult(result)).unwrap();
+            }
+            DownMessage::RejectedTarget(target) => {
+                println!("Target rejected: {:?}", target);
+                to.send(ServerMessage::RejectedTarget(target)).unwrap();
+            }
+            DownMessage::DurationStats(stats) => {
+                println!("Target durations: {:?}", stats);
+                to.send(ServerMessage::DurationStats(stats)).unwrap();
+            }
+        }
     }
 }
 
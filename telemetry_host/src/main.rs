@@ -46,9 +46,15 @@ fn main() {
         api::get_home,
         api::get_pulses,
         api::get_latest_pulses,
+        api::get_target_status,
+        api::get_duration_stats,
+        api::get_rejected_targets,
+        api::get_scan_result,
         api::do_reposition,
         api::motor_test,
         api::set_yaw,
+        api::start_bearing_sweep,
+        api::get_bearing_sweep,
         api::set_logging,
         api::set_waypoint,
         api::set_generic,
@@ -1,4 +1,4 @@
-pub use common::{Pulse, Timestamp};
+pub use common::{DurationStats, Pulse, RejectedTarget, ScanResult, TargetStatus, Timestamp};
 pub use common::UpMessage as PulseServerMessage;
 
 #[derive(Copy, Clone, Default, Serialize)]
@@ -36,6 +36,10 @@ pub struct Point {
 
 pub enum ServerMessage {
     Pulse(PulseWithTelemetry),
+    TargetStatus(TargetStatus),
+    ScanResult(ScanResult),
+    RejectedTarget(RejectedTarget),
+    DurationStats(DurationStats),
     PulseServer(PulseServerMessage),
 }
 
@@ -46,6 +50,77 @@ pub struct YawChange {
     pub direction: f32,
 }
 
+/// The configuration of a bearing sweep, where the drone rotates 360 degrees on the spot to
+/// estimate the bearing of each target from the signal strength of its pulses
+#[derive(Debug, Clone, Deserialize)]
+pub struct SweepConfig {
+    /// The rate (in degrees per second) to rotate at
+    pub turn_rate: f32,
+
+    /// The gain pattern of the directional antenna
+    #[serde(default)]
+    pub antenna: AntennaPattern,
+}
+
+/// A model of the gain pattern of a directional antenna
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct AntennaPattern {
+    /// The width (in degrees) of the main lobe between the -3 dB points
+    pub beamwidth: f32,
+
+    /// The gain (in dB) of the main lobe relative to the rest of the pattern
+    pub front_to_back: f32,
+
+    /// The direction (in degrees, clockwise) that the antenna points relative to the nose of the
+    /// drone
+    #[serde(default)]
+    pub offset: f32,
+}
+
+impl Default for AntennaPattern {
+    fn default() -> AntennaPattern {
+        AntennaPattern { beamwidth: 90.0, front_to_back: 15.0, offset: 0.0 }
+    }
+}
+
+/// The estimated bearing of a target from a bearing sweep
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct BearingEstimate {
+    pub target_id: usize,
+
+    /// The bearing (in degrees, clockwise from north) from the drone to the target
+    pub bearing: f32,
+
+    /// The range of bearings (in degrees, either side of `bearing`) that fit the data almost as
+    /// well as the estimate
+    pub uncertainty: f32,
+
+    /// How much the estimate can be trusted, from 0 to 1. This is the fraction of the variation in
+    /// signal strength explained by the antenna pattern, scaled by the fraction of headings that
+    /// pulses were received at.
+    pub confidence: f32,
+
+    /// The number of pulses used for the estimate
+    pub samples: usize,
+}
+
+/// The state of the most recent bearing sweep
+#[derive(Debug, Clone, Serialize)]
+pub enum SweepState {
+    Idle,
+    Running,
+    Complete,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BearingSweep {
+    pub state: SweepState,
+
+    /// The location of the drone when the sweep started
+    pub location: Option<Location>,
+    pub estimates: Vec<BearingEstimate>,
+}
+
 #[derive(Deserialize)]
 pub struct NavWaypoint {
     pub delay: f32,